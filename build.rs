@@ -0,0 +1,4 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tonic_build::compile_protos("proto/atlas_txn_sender.proto")?;
+    Ok(())
+}
@@ -0,0 +1,239 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use cadence_macros::statsd_count;
+use jsonrpsee::{
+    core::{async_trait, RpcResult},
+    proc_macros::rpc,
+};
+use serde::{Deserialize, Serialize};
+use solana_rpc_client_api::request::MAX_GET_SIGNATURE_STATUSES_QUERY_ITEMS;
+use tracing::info;
+
+use crate::{
+    errors::AtlasTxnSenderError, leader_tracker::LeaderTracker,
+    transaction_store::TransactionStore, txn_sender::TxnSender,
+};
+
+const DEFAULT_NUM_OLDEST: usize = 10;
+const MAX_NUM_OLDEST: usize = 1000;
+// leaders are polled 1000 slots ahead, we can't send to more than that
+const MAX_NUM_LEADERS: usize = 1000;
+const MIN_RETRY_INTERVAL_MS: u64 = 100;
+const MAX_RETRY_INTERVAL_MS: u64 = 60_000;
+const MAX_EVICT_SIGNATURES: usize = MAX_GET_SIGNATURE_STATUSES_QUERY_ITEMS;
+
+/// Percentiles summarizes a distribution of values
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Percentiles {
+    pub min: u64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct QueuedTransaction {
+    pub signature: String,
+    pub age_ms: u64,
+    pub retry_count: usize,
+    pub max_retries: Option<usize>,
+    pub client_id: Option<String>,
+    pub request_id: String,
+}
+
+/// QueueStats is a snapshot of the transactions currently in the retry queue
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueStats {
+    pub count: usize,
+    pub age_ms: Percentiles,
+    pub retry_count: Percentiles,
+    // keyed by max_retries, "unlimited" when the client didn't set one
+    pub count_by_max_retries: BTreeMap<String, usize>,
+    pub oldest: Vec<QueuedTransaction>,
+}
+
+/// SenderControls are the settings that can be changed at runtime through the admin api
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SenderControls {
+    pub paused: bool,
+    pub num_leaders: usize,
+    pub retry_interval_ms: u64,
+}
+
+#[rpc(server)]
+pub trait AtlasTxnSenderAdmin {
+    #[method(name = "getQueueStats")]
+    async fn get_queue_stats(&self, num_oldest: Option<usize>) -> RpcResult<QueueStats>;
+    #[method(name = "getSenderControls")]
+    async fn get_sender_controls(&self) -> RpcResult<SenderControls>;
+    /// pauseSending stops sending to leaders, new transactions are rejected and queued ones keep their retries
+    #[method(name = "pauseSending")]
+    async fn pause_sending(&self) -> RpcResult<SenderControls>;
+    #[method(name = "resumeSending")]
+    async fn resume_sending(&self) -> RpcResult<SenderControls>;
+    #[method(name = "setNumLeaders")]
+    async fn set_num_leaders(&self, num_leaders: usize) -> RpcResult<SenderControls>;
+    /// setRetryInterval changes how long the retry loop waits between passes over the queue
+    #[method(name = "setRetryInterval")]
+    async fn set_retry_interval(&self, retry_interval_ms: u64) -> RpcResult<SenderControls>;
+    /// drainRetryQueue stops retrying every queued transaction and returns how many there were
+    #[method(name = "drainRetryQueue")]
+    async fn drain_retry_queue(&self) -> RpcResult<usize>;
+    /// evictTransactions stops retrying and watching each signature, false for signatures the sender wasn't working on
+    #[method(name = "evictTransactions")]
+    async fn evict_transactions(&self, signatures: Vec<String>) -> RpcResult<Vec<bool>>;
+}
+
+pub struct AtlasTxnSenderAdminImpl {
+    transaction_store: Arc<dyn TransactionStore>,
+    txn_sender: Arc<dyn TxnSender>,
+    leader_tracker: Arc<dyn LeaderTracker>,
+}
+
+impl AtlasTxnSenderAdminImpl {
+    pub fn new(
+        transaction_store: Arc<dyn TransactionStore>,
+        txn_sender: Arc<dyn TxnSender>,
+        leader_tracker: Arc<dyn LeaderTracker>,
+    ) -> Self {
+        Self {
+            transaction_store,
+            txn_sender,
+            leader_tracker,
+        }
+    }
+
+    fn sender_controls(&self) -> SenderControls {
+        SenderControls {
+            paused: self.txn_sender.is_paused(),
+            num_leaders: self.leader_tracker.get_num_leaders(),
+            retry_interval_ms: self.txn_sender.get_retry_interval().as_millis() as u64,
+        }
+    }
+}
+
+#[async_trait]
+impl AtlasTxnSenderAdminServer for AtlasTxnSenderAdminImpl {
+    async fn get_queue_stats(&self, num_oldest: Option<usize>) -> RpcResult<QueueStats> {
+        statsd_count!("get_queue_stats", 1);
+        let num_oldest = num_oldest.unwrap_or(DEFAULT_NUM_OLDEST);
+        if num_oldest > MAX_NUM_OLDEST {
+            return Err(AtlasTxnSenderError::InvalidParams {
+                reason: format!("num_oldest too large; max {MAX_NUM_OLDEST}"),
+            }
+            .into());
+        }
+        let mut queued_transactions = vec![];
+        let mut count_by_max_retries = BTreeMap::new();
+        for transaction in self.transaction_store.get_transactions().iter() {
+            let max_retries = transaction
+                .max_retries
+                .map_or("unlimited".to_string(), |max_retries| max_retries.to_string());
+            *count_by_max_retries.entry(max_retries).or_insert(0) += 1;
+            queued_transactions.push(QueuedTransaction {
+                signature: transaction.key().clone(),
+                age_ms: transaction.sent_at.elapsed().as_millis() as u64,
+                retry_count: transaction.retry_count,
+                max_retries: transaction.max_retries,
+                client_id: transaction.client_id.clone(),
+                request_id: transaction.request_id.clone(),
+            });
+        }
+        let age_ms = percentiles(queued_transactions.iter().map(|t| t.age_ms).collect());
+        let retry_count = percentiles(
+            queued_transactions
+                .iter()
+                .map(|t| t.retry_count as u64)
+                .collect(),
+        );
+        let count = queued_transactions.len();
+        queued_transactions.sort_by(|a, b| b.age_ms.cmp(&a.age_ms));
+        queued_transactions.truncate(num_oldest);
+        Ok(QueueStats {
+            count,
+            age_ms,
+            retry_count,
+            count_by_max_retries,
+            oldest: queued_transactions,
+        })
+    }
+    async fn get_sender_controls(&self) -> RpcResult<SenderControls> {
+        Ok(self.sender_controls())
+    }
+    async fn pause_sending(&self) -> RpcResult<SenderControls> {
+        statsd_count!("pause_sending", 1);
+        info!("sending paused");
+        self.txn_sender.set_paused(true);
+        Ok(self.sender_controls())
+    }
+    async fn resume_sending(&self) -> RpcResult<SenderControls> {
+        statsd_count!("resume_sending", 1);
+        info!("sending resumed");
+        self.txn_sender.set_paused(false);
+        Ok(self.sender_controls())
+    }
+    async fn set_num_leaders(&self, num_leaders: usize) -> RpcResult<SenderControls> {
+        if num_leaders == 0 || num_leaders > MAX_NUM_LEADERS {
+            return Err(AtlasTxnSenderError::InvalidParams {
+                reason: format!("num_leaders must be between 1 and {MAX_NUM_LEADERS}"),
+            }
+            .into());
+        }
+        info!("num_leaders set to {}", num_leaders);
+        self.leader_tracker.set_num_leaders(num_leaders);
+        Ok(self.sender_controls())
+    }
+    async fn set_retry_interval(&self, retry_interval_ms: u64) -> RpcResult<SenderControls> {
+        if !(MIN_RETRY_INTERVAL_MS..=MAX_RETRY_INTERVAL_MS).contains(&retry_interval_ms) {
+            return Err(AtlasTxnSenderError::InvalidParams {
+                reason: format!(
+                    "retry_interval_ms must be between {MIN_RETRY_INTERVAL_MS} and {MAX_RETRY_INTERVAL_MS}"
+                ),
+            }
+            .into());
+        }
+        info!("retry interval set to {}ms", retry_interval_ms);
+        self.txn_sender
+            .set_retry_interval(Duration::from_millis(retry_interval_ms));
+        Ok(self.sender_controls())
+    }
+    async fn drain_retry_queue(&self) -> RpcResult<usize> {
+        statsd_count!("drain_retry_queue", 1);
+        let drained = self.txn_sender.drain_retry_queue();
+        info!("drained {} transactions from the retry queue", drained);
+        Ok(drained)
+    }
+    async fn evict_transactions(&self, signatures: Vec<String>) -> RpcResult<Vec<bool>> {
+        if signatures.len() > MAX_EVICT_SIGNATURES {
+            return Err(AtlasTxnSenderError::InvalidParams {
+                reason: format!("Too many signatures provided; max {MAX_EVICT_SIGNATURES}"),
+            }
+            .into());
+        }
+        statsd_count!("evict_transactions", signatures.len() as i64);
+        Ok(signatures
+            .iter()
+            .map(|signature| self.txn_sender.cancel_transaction(signature))
+            .collect())
+    }
+}
+
+fn percentiles(mut values: Vec<u64>) -> Percentiles {
+    if values.is_empty() {
+        return Percentiles::default();
+    }
+    values.sort_unstable();
+    let percentile = |p: usize| values[(values.len() - 1) * p / 100];
+    Percentiles {
+        min: values[0],
+        p50: percentile(50),
+        p90: percentile(90),
+        p99: percentile(99),
+        max: values[values.len() - 1],
+    }
+}
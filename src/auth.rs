@@ -0,0 +1,167 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use cadence_macros::statsd_count;
+use hyper::{Body, Method, Request, Response, StatusCode};
+use tower::{Layer, Service};
+use tower_http::validate_request::ValidateRequest;
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+pub const API_KEY_QUERY_PARAM: &str = "api-key";
+// used in metrics for requests that aren't attributed to a client
+pub const ANONYMOUS_CLIENT: &str = "anonymous";
+
+tokio::task_local! {
+    static CLIENT_ID: String;
+}
+
+/// current_client_id returns the client of the request being handled, None if auth is disabled
+pub fn current_client_id() -> Option<String> {
+    CLIENT_ID.try_with(|client_id| client_id.clone()).ok()
+}
+
+/// current_client_tag returns the client to attribute the current request to in metrics
+pub fn current_client_tag() -> String {
+    current_client_id().unwrap_or_else(|| ANONYMOUS_CLIENT.to_string())
+}
+
+/// with_client_id runs the future with client_id as the current client
+pub async fn with_client_id<F: Future>(client_id: Option<String>, f: F) -> F::Output {
+    match client_id {
+        Some(client_id) => CLIENT_ID.scope(client_id, f).await,
+        None => f.await,
+    }
+}
+
+/// ClientId is added to the request extensions once the api key is validated
+#[derive(Clone, Debug)]
+pub struct ClientId(pub String);
+
+/// ApiKeys maps api keys to the client they belong to, an empty set of keys disables auth
+#[derive(Clone, Default)]
+pub struct ApiKeys {
+    keys: Arc<HashMap<String, String>>,
+}
+
+impl ApiKeys {
+    /// parses keys formatted as `client_a:key_a,client_b:key_b`
+    pub fn parse(api_keys: &str) -> anyhow::Result<Self> {
+        let mut keys = HashMap::new();
+        for entry in api_keys.split(',').filter(|entry| !entry.trim().is_empty()) {
+            let (client_id, key) = entry
+                .trim()
+                .split_once(':')
+                .ok_or_else(|| anyhow::anyhow!("api key entry must be client:key, got {entry}"))?;
+            if client_id.is_empty() || key.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "api key entry must have a client and a key, got {entry}"
+                ));
+            }
+            if let Some(existing) = keys.insert(key.to_string(), client_id.to_string()) {
+                return Err(anyhow::anyhow!(
+                    "api key for {client_id} is already used by {existing}"
+                ));
+            }
+        }
+        Ok(Self {
+            keys: Arc::new(keys),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    pub fn client_id(&self, key: &str) -> Option<&str> {
+        self.keys.get(key).map(|client_id| client_id.as_str())
+    }
+}
+
+impl ValidateRequest<Body> for ApiKeys {
+    type ResponseBody = Body;
+
+    fn validate(&mut self, request: &mut Request<Body>) -> Result<(), Response<Body>> {
+        // load balancer health checks don't carry keys
+        let is_health_check =
+            request.method() == Method::GET && request.uri().path() == "/health";
+        if !self.is_enabled() || is_health_check {
+            return Ok(());
+        }
+        let key = request
+            .headers()
+            .get(API_KEY_HEADER)
+            .and_then(|key| key.to_str().ok())
+            .or_else(|| {
+                request.uri().query().and_then(|query| {
+                    query.split('&').find_map(|pair| {
+                        pair.strip_prefix(API_KEY_QUERY_PARAM)
+                            .and_then(|rest| rest.strip_prefix('='))
+                    })
+                })
+            });
+        match key.and_then(|key| self.client_id(key)) {
+            Some(client_id) => {
+                let client_id = ClientId(client_id.to_string());
+                request.extensions_mut().insert(client_id);
+                Ok(())
+            }
+            None => {
+                statsd_count!("unauthorized_request", 1);
+                let mut response = Response::new(Body::from("Unauthorized"));
+                *response.status_mut() = StatusCode::UNAUTHORIZED;
+                Err(response)
+            }
+        }
+    }
+}
+
+/// ClientIdentityLayer makes the ClientId set by ApiKeys available to rpc methods through current_client_id
+#[derive(Clone, Default)]
+pub struct ClientIdentityLayer;
+
+impl<S> Layer<S> for ClientIdentityLayer {
+    type Service = ClientIdentity<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ClientIdentity { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct ClientIdentity<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for ClientIdentity<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+    S::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = Box<dyn Error + Send + Sync + 'static>;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let client_id = req
+            .extensions()
+            .get::<ClientId>()
+            .map(|client_id| client_id.0.clone());
+        let fut = self.inner.call(req);
+        // http requests are handled inside this future, so the task local is visible to the rpc methods
+        Box::pin(with_client_id(client_id, async move {
+            fut.await.map_err(Into::into)
+        }))
+    }
+}
@@ -1,18 +1,174 @@
 use std::error::Error;
 
 use jsonrpsee::types::{error::INVALID_PARAMS_CODE, ErrorObjectOwned};
+use serde_json::json;
+use solana_rpc_client_api::{
+    custom_error::{
+        JSON_RPC_SERVER_ERROR_MIN_CONTEXT_SLOT_NOT_REACHED, JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY,
+        JSON_RPC_SERVER_ERROR_SEND_TRANSACTION_PREFLIGHT_FAILURE,
+        JSON_RPC_SERVER_ERROR_TRANSACTION_SIGNATURE_VERIFICATION_FAILURE,
+    },
+    response::RpcSimulateTransactionResult,
+};
+use solana_sdk::{clock::Slot, transaction::TransactionError};
 
-pub fn invalid_request(reason: &str) -> ErrorObjectOwned {
-    ErrorObjectOwned::owned(
-        INVALID_PARAMS_CODE,
-        format!("Invalid Request: {reason}"),
-        None::<String>,
-    )
-}
+use crate::rpc_server::HealthReport;
+
+// codes for errors solana rpc doesn't have, kept stable so clients can match on them. They're in the json rpc
+// server error range, clear of the -32001..-32018 codes solana rpc uses
+pub const DECODE_ERROR_CODE: i32 = -32050;
+pub const UNSUPPORTED_CONFIG_CODE: i32 = -32051;
+pub const POLICY_REJECTED_CODE: i32 = -32052;
+pub const NO_LEADERS_AVAILABLE_CODE: i32 = -32053;
+pub const UPSTREAM_RPC_ERROR_CODE: i32 = -32054;
+pub const SENDING_PAUSED_CODE: i32 = -32055;
+pub const BLOCKHASH_NOT_FOUND_CODE: i32 = -32056;
+pub const BLOCKHASH_EXPIRED_CODE: i32 = -32057;
+pub const UNAUTHORIZED_CODE: i32 = -32058;
+pub const RATE_LIMITED_CODE: i32 = -32059;
+pub const NONCE_ADVANCED_CODE: i32 = -32060;
 
-#[derive(Debug)]
+/// AtlasTxnSenderError is every error the sender returns, each variant maps to a stable json rpc code
+#[derive(Debug, Clone)]
 pub enum AtlasTxnSenderError {
-    Custom(String),
+    /// the request arguments are invalid
+    InvalidParams { reason: String },
+    /// the transaction couldn't be decoded or deserialized
+    Decode { reason: String },
+    /// a config field is set to something the sender doesn't support
+    UnsupportedConfig { field: String, reason: String },
+    /// the transaction isn't allowed for this client
+    PolicyRejected { reason: String },
+    /// the request isn't attributed to a client while api keys are required
+    Unauthorized { reason: String },
+    /// we don't know of any leader we could send the transaction to
+    NoLeadersAvailable,
+    /// the client is over one of its limits
+    RateLimited { limit: String, reason: String },
+    /// the transaction failed simulation, same as solana rpc's sendTransaction
+    PreflightFailure {
+        err: TransactionError,
+        result: RpcSimulateTransactionResult,
+    },
+    /// the slot hasn't reached the request's min_context_slot, same as solana rpc. context_slot is None
+    /// when the upstream rpc rejected the preflight for it
+    MinContextSlotNotReached { context_slot: Option<Slot> },
+    /// the sender isn't ready to take traffic, same as solana rpc's getHealth
+    NodeUnhealthy { report: HealthReport },
+    /// a call to the upstream rpc failed
+    UpstreamRpc { reason: String },
+    /// an operator paused sending through the admin api
+    SendingPaused,
+    /// the transaction's recent blockhash isn't from any recent block
+    BlockhashNotFound { blockhash: String },
+    /// the transaction's recent blockhash is too old for it to land
+    BlockhashExpired {
+        blockhash: String,
+        last_valid_block_height: u64,
+        block_height: u64,
+    },
+    /// the durable nonce the transaction uses was already advanced by another transaction
+    NonceAdvanced { nonce_account: String, nonce: String },
+    /// a signature doesn't match its signer and the message, same code as solana rpc
+    SignatureVerificationFailure { invalid_signatures: Vec<usize> },
+}
+
+impl AtlasTxnSenderError {
+    pub fn code(&self) -> i32 {
+        match self {
+            AtlasTxnSenderError::InvalidParams { .. } => INVALID_PARAMS_CODE,
+            AtlasTxnSenderError::Decode { .. } => DECODE_ERROR_CODE,
+            AtlasTxnSenderError::UnsupportedConfig { .. } => UNSUPPORTED_CONFIG_CODE,
+            AtlasTxnSenderError::PolicyRejected { .. } => POLICY_REJECTED_CODE,
+            AtlasTxnSenderError::Unauthorized { .. } => UNAUTHORIZED_CODE,
+            AtlasTxnSenderError::NoLeadersAvailable => NO_LEADERS_AVAILABLE_CODE,
+            AtlasTxnSenderError::RateLimited { .. } => RATE_LIMITED_CODE,
+            AtlasTxnSenderError::PreflightFailure { .. } => {
+                JSON_RPC_SERVER_ERROR_SEND_TRANSACTION_PREFLIGHT_FAILURE as i32
+            }
+            AtlasTxnSenderError::MinContextSlotNotReached { .. } => {
+                JSON_RPC_SERVER_ERROR_MIN_CONTEXT_SLOT_NOT_REACHED as i32
+            }
+            AtlasTxnSenderError::NodeUnhealthy { .. } => JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY as i32,
+            AtlasTxnSenderError::UpstreamRpc { .. } => UPSTREAM_RPC_ERROR_CODE,
+            AtlasTxnSenderError::SendingPaused => SENDING_PAUSED_CODE,
+            AtlasTxnSenderError::BlockhashNotFound { .. } => BLOCKHASH_NOT_FOUND_CODE,
+            AtlasTxnSenderError::BlockhashExpired { .. } => BLOCKHASH_EXPIRED_CODE,
+            AtlasTxnSenderError::NonceAdvanced { .. } => NONCE_ADVANCED_CODE,
+            AtlasTxnSenderError::SignatureVerificationFailure { .. } => {
+                JSON_RPC_SERVER_ERROR_TRANSACTION_SIGNATURE_VERIFICATION_FAILURE as i32
+            }
+        }
+    }
+
+    /// retryable is true when sending the same request again later can succeed
+    pub fn retryable(&self) -> bool {
+        matches!(
+            self,
+            AtlasTxnSenderError::NoLeadersAvailable
+                | AtlasTxnSenderError::RateLimited { .. }
+                | AtlasTxnSenderError::MinContextSlotNotReached { .. }
+                | AtlasTxnSenderError::NodeUnhealthy { .. }
+                | AtlasTxnSenderError::UpstreamRpc { .. }
+                | AtlasTxnSenderError::SendingPaused
+        )
+    }
+
+    /// data is the `data` field of the json rpc error, errors solana rpc also returns keep its payload,
+    /// everything else gets the kind of error and whether it's retryable
+    pub fn data(&self) -> serde_json::Value {
+        let kind = match self {
+            AtlasTxnSenderError::InvalidParams { .. } => "invalidParams",
+            AtlasTxnSenderError::Decode { .. } => "decode",
+            AtlasTxnSenderError::UnsupportedConfig { .. } => "unsupportedConfig",
+            AtlasTxnSenderError::PolicyRejected { .. } => "policyRejected",
+            AtlasTxnSenderError::Unauthorized { .. } => "unauthorized",
+            AtlasTxnSenderError::NoLeadersAvailable => "noLeadersAvailable",
+            AtlasTxnSenderError::RateLimited { .. } => "rateLimited",
+            AtlasTxnSenderError::PreflightFailure { result, .. } => return json!(result),
+            AtlasTxnSenderError::MinContextSlotNotReached { context_slot } => {
+                return json!({ "contextSlot": context_slot })
+            }
+            AtlasTxnSenderError::NodeUnhealthy { report } => return json!(report),
+            AtlasTxnSenderError::UpstreamRpc { .. } => "upstreamRpc",
+            AtlasTxnSenderError::SendingPaused => "sendingPaused",
+            AtlasTxnSenderError::BlockhashNotFound { .. } => "blockhashNotFound",
+            AtlasTxnSenderError::BlockhashExpired { .. } => "blockhashExpired",
+            AtlasTxnSenderError::NonceAdvanced { .. } => "nonceAdvanced",
+            AtlasTxnSenderError::SignatureVerificationFailure { .. } => {
+                "signatureVerificationFailure"
+            }
+        };
+        let mut data = json!({ "kind": kind, "retryable": self.retryable() });
+        match self {
+            AtlasTxnSenderError::UnsupportedConfig { field, .. } => data["field"] = json!(field),
+            AtlasTxnSenderError::RateLimited { limit, .. } => data["limit"] = json!(limit),
+            AtlasTxnSenderError::BlockhashNotFound { blockhash } => {
+                data["blockhash"] = json!(blockhash)
+            }
+            AtlasTxnSenderError::BlockhashExpired {
+                blockhash,
+                last_valid_block_height,
+                block_height,
+            } => {
+                data["blockhash"] = json!(blockhash);
+                data["lastValidBlockHeight"] = json!(last_valid_block_height);
+                data["blockHeight"] = json!(block_height);
+            }
+            AtlasTxnSenderError::NonceAdvanced {
+                nonce_account,
+                nonce,
+            } => {
+                data["nonceAccount"] = json!(nonce_account);
+                data["nonce"] = json!(nonce);
+            }
+            AtlasTxnSenderError::SignatureVerificationFailure { invalid_signatures } => {
+                data["invalidSignatures"] = json!(invalid_signatures)
+            }
+            _ => {}
+        }
+        data
+    }
 }
 
 impl Error for AtlasTxnSenderError {}
@@ -20,13 +176,54 @@ impl Error for AtlasTxnSenderError {}
 impl std::fmt::Display for AtlasTxnSenderError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            AtlasTxnSenderError::Custom(msg) => write!(f, "{}", msg),
+            AtlasTxnSenderError::InvalidParams { reason } => write!(f, "Invalid Request: {reason}"),
+            AtlasTxnSenderError::Decode { reason } => write!(f, "Invalid Transaction: {reason}"),
+            AtlasTxnSenderError::UnsupportedConfig { field, reason } => {
+                write!(f, "Unsupported {field}: {reason}")
+            }
+            AtlasTxnSenderError::PolicyRejected { reason } => {
+                write!(f, "Rejected by policy: {reason}")
+            }
+            AtlasTxnSenderError::Unauthorized { reason } => write!(f, "Unauthorized: {reason}"),
+            AtlasTxnSenderError::NoLeadersAvailable => {
+                write!(f, "No leaders available to send the transaction to")
+            }
+            AtlasTxnSenderError::RateLimited { reason, .. } => write!(f, "Rate limited: {reason}"),
+            AtlasTxnSenderError::PreflightFailure { err, .. } => {
+                write!(f, "Transaction simulation failed: {err}")
+            }
+            AtlasTxnSenderError::MinContextSlotNotReached { .. } => {
+                write!(f, "Minimum context slot has not been reached")
+            }
+            AtlasTxnSenderError::NodeUnhealthy { .. } => write!(f, "Node is unhealthy"),
+            AtlasTxnSenderError::UpstreamRpc { reason } => {
+                write!(f, "Upstream RPC error: {reason}")
+            }
+            AtlasTxnSenderError::SendingPaused => write!(f, "Sending is paused"),
+            AtlasTxnSenderError::BlockhashNotFound { blockhash } => {
+                write!(f, "Blockhash not found: {blockhash}")
+            }
+            AtlasTxnSenderError::BlockhashExpired {
+                last_valid_block_height,
+                block_height,
+                ..
+            } => write!(
+                f,
+                "Blockhash expired: last valid block height {last_valid_block_height}, current block height {block_height}"
+            ),
+            AtlasTxnSenderError::NonceAdvanced {
+                nonce_account,
+                nonce,
+            } => write!(f, "Durable nonce advanced: {nonce_account} holds nonce {nonce}"),
+            AtlasTxnSenderError::SignatureVerificationFailure { .. } => {
+                write!(f, "Transaction signature verification failure")
+            }
         }
     }
 }
 
-impl From<String> for AtlasTxnSenderError {
-    fn from(msg: String) -> Self {
-        AtlasTxnSenderError::Custom(msg)
+impl From<AtlasTxnSenderError> for ErrorObjectOwned {
+    fn from(e: AtlasTxnSenderError) -> Self {
+        ErrorObjectOwned::owned(e.code(), e.to_string(), Some(e.data()))
     }
 }
@@ -1,35 +1,149 @@
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
 use std::time::Instant;
 use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use cadence_macros::statsd_count;
-use dashmap::DashMap;
+use dashmap::{mapref::entry::Entry, DashMap};
 use futures::sink::SinkExt;
 use futures::StreamExt;
 use rand::distributions::Alphanumeric;
 use rand::Rng;
-use solana_sdk::clock::UnixTimestamp;
-use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::clock::MAX_PROCESSING_AGE;
+use solana_sdk::nonce::{state::Versions, State};
+use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::Signature;
-use tokio::{sync::RwLock, time::sleep};
-use tonic::async_trait;
+use solana_sdk::transaction::TransactionError;
+use tokio::{
+    sync::{Notify, RwLock},
+    time::sleep,
+};
 use tracing::{error, info};
 use yellowstone_grpc_client::GeyserGrpcClient;
-use yellowstone_grpc_proto::geyser::SubscribeRequestFilterBlocks;
+use yellowstone_grpc_proto::geyser::{SubscribeRequestFilterAccounts, SubscribeRequestFilterBlocks};
 use yellowstone_grpc_proto::{
     geyser::{
         subscribe_update::UpdateOneof, CommitmentLevel, SubscribeRequest,
-        SubscribeRequestFilterSlots, SubscribeRequestPing,
+        SubscribeRequestFilterBlocksMeta, SubscribeRequestFilterSlots, SubscribeRequestPing,
     },
     tonic::service::Interceptor,
 };
 
-use crate::solana_rpc::SolanaRpc;
+use crate::{
+    solana_rpc::{
+        BlockhashStatus, DurableNonce, LandedTransaction, RecentBlock, SolanaRpc, StreamHealth,
+    },
+    utils::now_unix_millis,
+};
+
+// blockhashes are kept for twice as long as they're valid, so we can tell expired ones from unknown ones
+const BLOCKHASH_RETENTION: u64 = 2 * MAX_PROCESSING_AGE as u64;
+
+/// RecentBlockhashes is a rolling cache of the blockhashes of confirmed blocks, and of processed blocks so
+/// blockhashes clients fetched at processed commitment are known before their block is confirmed
+#[derive(Default)]
+struct RecentBlockhashes {
+    latest_block: Option<RecentBlock>,
+    // blockhash -> block height
+    block_heights: HashMap<String, u64>,
+    // every block from this block height on was seen, reset when we skip blocks
+    complete_since: u64,
+}
+
+impl RecentBlockhashes {
+    fn insert(&mut self, block: RecentBlock) {
+        self.block_heights
+            .insert(block.blockhash.clone(), block.block_height);
+        let latest_block_height = self.latest_block.as_ref().map(|latest| latest.block_height);
+        match latest_block_height {
+            // blocks from a fork we already passed don't move the latest block
+            Some(latest_block_height) if block.block_height <= latest_block_height => return,
+            Some(latest_block_height) if block.block_height == latest_block_height + 1 => {}
+            _ => self.complete_since = block.block_height,
+        }
+        let block_height = block.block_height;
+        self.latest_block = Some(block);
+        self.block_heights
+            .retain(|_, height| *height + BLOCKHASH_RETENTION >= block_height);
+    }
+
+    /// insert_processed adds the blockhash of a processed block, it doesn't move the latest block since the
+    /// block may not be confirmed
+    fn insert_processed(&mut self, blockhash: String, block_height: u64) {
+        self.block_heights.entry(blockhash).or_insert(block_height);
+        // keeps the cache bounded while the block stream is down
+        self.block_heights
+            .retain(|_, height| *height + BLOCKHASH_RETENTION >= block_height);
+    }
+
+    fn status(&self, blockhash: &str) -> BlockhashStatus {
+        let Some(latest_block) = self.latest_block.as_ref() else {
+            return BlockhashStatus::Unknown;
+        };
+        match self.block_heights.get(blockhash) {
+            Some(height) => {
+                let last_valid_block_height = height + MAX_PROCESSING_AGE as u64;
+                if latest_block.block_height > last_valid_block_height {
+                    BlockhashStatus::Expired {
+                        last_valid_block_height,
+                        block_height: latest_block.block_height,
+                    }
+                } else {
+                    BlockhashStatus::Valid {
+                        last_valid_block_height,
+                    }
+                }
+            }
+            // any blockhash that's still valid is from a block we've seen
+            None if latest_block.block_height >= self.complete_since + MAX_PROCESSING_AGE as u64 => {
+                BlockhashStatus::NotFound
+            }
+            None => BlockhashStatus::Unknown,
+        }
+    }
+}
+
+/// StreamState tracks whether a subscription is up and when it last got a message
+#[derive(Default)]
+struct StreamState {
+    connected: AtomicBool,
+    // unix timestamp in milliseconds, 0 until the first message
+    last_message_at: AtomicU64,
+}
+
+impl StreamState {
+    fn set_connected(&self, connected: bool) {
+        self.connected.store(connected, Ordering::Relaxed);
+    }
+
+    fn on_message(&self) {
+        self.last_message_at
+            .store(now_unix_millis(), Ordering::Relaxed);
+    }
+
+    fn health(&self) -> StreamHealth {
+        let last_message_at = self.last_message_at.load(Ordering::Relaxed);
+        let last_message_at = (last_message_at > 0).then_some(last_message_at);
+        StreamHealth {
+            connected: self.connected.load(Ordering::Relaxed),
+            last_message_at,
+            last_message_age_ms: last_message_at.map(|t| now_unix_millis().saturating_sub(t)),
+        }
+    }
+}
 
 pub struct GrpcGeyserImpl<T> {
     grpc_client: Arc<RwLock<GeyserGrpcClient<T>>>,
     cur_slot: Arc<AtomicU64>,
-    signature_cache: Arc<DashMap<String, (UnixTimestamp, Instant)>>,
+    signature_cache: Arc<DashMap<String, (LandedTransaction, Instant)>>,
+    recent_blockhashes: Arc<Mutex<RecentBlockhashes>>,
+    slot_stream: Arc<StreamState>,
+    block_stream: Arc<StreamState>,
+    // nonce account -> how many transactions are watching it
+    nonce_accounts: Arc<DashMap<String, usize>>,
+    durable_nonces: Arc<DashMap<String, DurableNonce>>,
+    // the nonce account subscription is resent whenever an account is added or removed
+    nonce_accounts_changed: Arc<Notify>,
 }
 
 impl<T: Interceptor + Send + Sync + 'static> GrpcGeyserImpl<T> {
@@ -38,11 +152,19 @@ impl<T: Interceptor + Send + Sync + 'static> GrpcGeyserImpl<T> {
             grpc_client,
             cur_slot: Arc::new(AtomicU64::new(0)),
             signature_cache: Arc::new(DashMap::new()),
+            recent_blockhashes: Arc::new(Mutex::new(RecentBlockhashes::default())),
+            slot_stream: Arc::new(StreamState::default()),
+            block_stream: Arc::new(StreamState::default()),
+            nonce_accounts: Arc::new(DashMap::new()),
+            durable_nonces: Arc::new(DashMap::new()),
+            nonce_accounts_changed: Arc::new(Notify::new()),
         };
         // polling with processed commitment to get latest leaders
         grpc_geyser.poll_slots();
         // polling with confirmed commitment to get confirmed transactions
         grpc_geyser.poll_blocks();
+        // polling with confirmed commitment to see durable nonce transactions' nonce accounts advance
+        grpc_geyser.poll_nonce_accounts();
         grpc_geyser.clean_signature_cache();
         grpc_geyser
     }
@@ -61,6 +183,8 @@ impl<T: Interceptor + Send + Sync + 'static> GrpcGeyserImpl<T> {
     fn poll_blocks(&self) {
         let grpc_client = self.grpc_client.clone();
         let signature_cache = self.signature_cache.clone();
+        let recent_blockhashes = self.recent_blockhashes.clone();
+        let block_stream = self.block_stream.clone();
         tokio::spawn(async move {
             loop {
                 let mut grpc_tx;
@@ -78,46 +202,149 @@ impl<T: Interceptor + Send + Sync + 'static> GrpcGeyserImpl<T> {
                     }
                     (grpc_tx, grpc_rx) = subscription.unwrap();
                 }
+                block_stream.set_connected(true);
                 while let Some(message) = grpc_rx.next().await {
                     match message {
-                        Ok(message) => match message.update_oneof {
-                            Some(UpdateOneof::Block(block)) => {
-                                let block_time = block.block_time.unwrap().timestamp;
-                                for transaction in block.transactions {
-                                    let signature =
-                                        Signature::new(&transaction.signature).to_string();
-                                    signature_cache.insert(signature, (block_time, Instant::now()));
+                        Ok(message) => {
+                            block_stream.on_message();
+                            match message.update_oneof {
+                                Some(UpdateOneof::Block(block)) => {
+                                    if let Some(block_height) = block.block_height.as_ref() {
+                                        recent_blockhashes.lock().unwrap().insert(RecentBlock {
+                                            slot: block.slot,
+                                            blockhash: block.blockhash.clone(),
+                                            block_height: block_height.block_height,
+                                        });
+                                    }
+                                    for transaction in block.transactions {
+                                        let signature =
+                                            Signature::new(&transaction.signature).to_string();
+                                        // the error is bincode encoded by geyser, same as the storage proto
+                                        let err = transaction
+                                            .meta
+                                            .and_then(|meta| meta.err)
+                                            .and_then(|err| {
+                                                bincode::deserialize::<TransactionError>(&err.err).ok()
+                                            });
+                                        let landed_transaction = LandedTransaction {
+                                            slot: block.slot,
+                                            err,
+                                        };
+                                        signature_cache
+                                            .insert(signature, (landed_transaction, Instant::now()));
+                                    }
                                 }
-                            }
-                            Some(UpdateOneof::Ping(_)) => {
-                                // This is necessary to keep load balancers that expect client pings alive. If your load balancer doesn't
-                                // require periodic client pings then this is unnecessary
-                                let ping = grpc_tx.send(ping()).await;
-                                if let Err(e) = ping {
-                                    error!("Error sending ping: {}", e);
-                                    statsd_count!("grpc_ping_error", 1);
-                                    break;
+                                Some(UpdateOneof::Ping(_)) => {
+                                    // This is necessary to keep load balancers that expect client pings alive. If your load balancer doesn't
+                                    // require periodic client pings then this is unnecessary
+                                    let ping = grpc_tx.send(ping()).await;
+                                    if let Err(e) = ping {
+                                        error!("Error sending ping: {}", e);
+                                        statsd_count!("grpc_ping_error", 1);
+                                        break;
+                                    }
+                                }
+                                Some(UpdateOneof::Pong(_)) => {}
+                                _ => {
+                                    error!("Unknown message: {:?}", message);
                                 }
                             }
-                            Some(UpdateOneof::Pong(_)) => {}
-                            _ => {
-                                error!("Unknown message: {:?}", message);
-                            }
-                        },
+                        }
                         Err(error) => {
                             error!("error in txn subscribe, resubscribing in 1 second: {error:?}");
                             sleep(Duration::from_secs(1)).await;
                         }
                     }
                 }
+                block_stream.set_connected(false);
                 error!("exiting loop");
             }
         });
     }
 
+    fn poll_nonce_accounts(&self) {
+        let grpc_client = self.grpc_client.clone();
+        let nonce_accounts = self.nonce_accounts.clone();
+        let durable_nonces = self.durable_nonces.clone();
+        let nonce_accounts_changed = self.nonce_accounts_changed.clone();
+        tokio::spawn(async move {
+            loop {
+                let mut grpc_tx;
+                let mut grpc_rx;
+                {
+                    let mut grpc_client = grpc_client.write().await;
+                    let subscription = grpc_client.subscribe().await;
+                    if let Err(e) = subscription {
+                        error!("Error subscribing to gRPC stream, waiting one second then retrying connect: {}", e);
+                        statsd_count!("grpc_subscribe_error", 1);
+                        sleep(Duration::from_secs(1)).await;
+                        continue;
+                    }
+                    (grpc_tx, grpc_rx) = subscription.unwrap();
+                }
+                let mut request = Some(get_nonce_accounts_subscribe_request(&nonce_accounts));
+                loop {
+                    if let Some(request) = request.take() {
+                        if let Err(e) = grpc_tx.send(request).await {
+                            error!("Error updating nonce account subscription: {}", e);
+                            break;
+                        }
+                    }
+                    tokio::select! {
+                        // a new request replaces the accounts filter of the previous one
+                        _ = nonce_accounts_changed.notified() => {
+                            request = Some(get_nonce_accounts_subscribe_request(&nonce_accounts));
+                        }
+                        message = grpc_rx.next() => match message {
+                            Some(Ok(msg)) => match msg.update_oneof {
+                                Some(UpdateOneof::Account(update)) => {
+                                    let nonce_account = update
+                                        .account
+                                        .as_ref()
+                                        .and_then(|account| Pubkey::try_from(account.pubkey.as_slice()).ok())
+                                        .map(|pubkey| pubkey.to_string());
+                                    let nonce = update
+                                        .account
+                                        .as_ref()
+                                        .and_then(|account| parse_durable_nonce(&account.data));
+                                    // updates can still arrive for accounts unwatched since the last request
+                                    if let Some((nonce_account, nonce)) = nonce_account
+                                        .zip(nonce)
+                                        .filter(|(nonce_account, _)| nonce_accounts.contains_key(nonce_account))
+                                    {
+                                        durable_nonces.insert(nonce_account, DurableNonce { nonce, slot: update.slot });
+                                    }
+                                }
+                                Some(UpdateOneof::Ping(_)) => {
+                                    // This is necessary to keep load balancers that expect client pings alive. If your load balancer doesn't
+                                    // require periodic client pings then this is unnecessary
+                                    request = Some(ping());
+                                }
+                                Some(UpdateOneof::Pong(_)) => {}
+                                _ => {
+                                    error!("Unknown message: {:?}", msg);
+                                }
+                            },
+                            Some(Err(error)) => {
+                                error!("error: {error:?}");
+                                break;
+                            }
+                            None => break,
+                        },
+                    }
+                }
+                // nonce updates missed while disconnected aren't replayed, those transactions run until their deadline
+                info!("gRPC nonce account stream disconnected, reconnecting in one second");
+                sleep(Duration::from_secs(1)).await;
+            }
+        });
+    }
+
     fn poll_slots(&self) {
         let grpc_client = self.grpc_client.clone();
         let cur_slot = self.cur_slot.clone();
+        let slot_stream = self.slot_stream.clone();
+        let recent_blockhashes = self.recent_blockhashes.clone();
         // let grpc_tx = self.grpc_tx.clone();
         tokio::spawn(async move {
             loop {
@@ -135,13 +362,23 @@ impl<T: Interceptor + Send + Sync + 'static> GrpcGeyserImpl<T> {
                     (grpc_tx, grpc_rx) = subscription.unwrap();
                 }
                 grpc_tx.send(get_slot_subscribe_request()).await.unwrap();
+                slot_stream.set_connected(true);
                 while let Some(message) = grpc_rx.next().await {
                     match message {
                         Ok(msg) => {
+                            slot_stream.on_message();
                             match msg.update_oneof {
                                 Some(UpdateOneof::Slot(slot)) => {
                                     cur_slot.store(slot.slot, Ordering::Relaxed);
                                 }
+                                Some(UpdateOneof::BlockMeta(block_meta)) => {
+                                    if let Some(block_height) = block_meta.block_height {
+                                        recent_blockhashes
+                                            .lock()
+                                            .unwrap()
+                                            .insert_processed(block_meta.blockhash, block_height.block_height);
+                                    }
+                                }
                                 Some(UpdateOneof::Ping(_)) => {
                                     // This is necessary to keep load balancers that expect client pings alive. If your load balancer doesn't
                                     // require periodic client pings then this is unnecessary
@@ -164,6 +401,7 @@ impl<T: Interceptor + Send + Sync + 'static> GrpcGeyserImpl<T> {
                         }
                     }
                 }
+                slot_stream.set_connected(false);
                 info!("gRPC stream disconnected, reconnecting in one second");
                 sleep(Duration::from_secs(1)).await;
             }
@@ -171,30 +409,48 @@ impl<T: Interceptor + Send + Sync + 'static> GrpcGeyserImpl<T> {
     }
 }
 
-#[async_trait]
 impl<T: Interceptor + Send + Sync> SolanaRpc for GrpcGeyserImpl<T> {
-    async fn confirm_transaction(&self, signature: String) -> Option<UnixTimestamp> {
-        let start = Instant::now();
-        // in practice if a tx doesn't land in less than 60 seconds it's probably not going to land
-        while start.elapsed() < Duration::from_secs(60) {
-            if let Some(block_time) = self.signature_cache.get(&signature) {
-                return Some(block_time.0.clone());
-            }
-            sleep(Duration::from_millis(200)).await;
+    fn get_landed_transaction(&self, signature: &str) -> Option<LandedTransaction> {
+        self.signature_cache
+            .get(signature)
+            .map(|landed_transaction| landed_transaction.0.clone())
+    }
+    fn get_slot_stream_health(&self) -> StreamHealth {
+        self.slot_stream.health()
+    }
+    fn get_block_stream_health(&self) -> StreamHealth {
+        self.block_stream.health()
+    }
+    fn get_latest_block(&self) -> Option<RecentBlock> {
+        self.recent_blockhashes.lock().unwrap().latest_block.clone()
+    }
+    fn get_blockhash_status(&self, blockhash: &str) -> BlockhashStatus {
+        self.recent_blockhashes.lock().unwrap().status(blockhash)
+    }
+    fn watch_nonce_account(&self, nonce_account: &str) {
+        let mut watchers = self
+            .nonce_accounts
+            .entry(nonce_account.to_string())
+            .or_insert(0);
+        *watchers += 1;
+        if *watchers == 1 {
+            self.nonce_accounts_changed.notify_one();
         }
-        return None;
-    }
-    // unused for now
-     async fn confirm_transaction_with_commitment(&self, signature: String, _commitment_config: CommitmentConfig) -> Option<UnixTimestamp> { 
-        let start = Instant::now();
-        // in practice if a tx doesn't land in less than 60 seconds it's probably not going to land
-        while start.elapsed() < Duration::from_secs(60) {
-            if let Some(block_time) = self.signature_cache.get(&signature) {
-                return Some(block_time.0.clone());
+    }
+    fn unwatch_nonce_account(&self, nonce_account: &str) {
+        if let Entry::Occupied(mut watchers) = self.nonce_accounts.entry(nonce_account.to_string()) {
+            *watchers.get_mut() -= 1;
+            if *watchers.get() == 0 {
+                watchers.remove();
+                self.durable_nonces.remove(nonce_account);
+                self.nonce_accounts_changed.notify_one();
             }
-            sleep(Duration::from_millis(200)).await;
         }
-        return None;
+    }
+    fn get_durable_nonce(&self, nonce_account: &str) -> Option<DurableNonce> {
+        self.durable_nonces
+            .get(nonce_account)
+            .map(|durable_nonce| durable_nonce.clone())
     }
     fn get_next_slot(&self) -> Option<u64> {
         let cur_slot = self.cur_slot.load(Ordering::Relaxed);
@@ -229,6 +485,37 @@ fn get_block_subscribe_request() -> SubscribeRequest {
     }
 }
 
+fn get_nonce_accounts_subscribe_request(nonce_accounts: &DashMap<String, usize>) -> SubscribeRequest {
+    let accounts: Vec<String> = nonce_accounts
+        .iter()
+        .map(|nonce_account| nonce_account.key().clone())
+        .collect();
+    // an accounts filter without accounts matches every account
+    if accounts.is_empty() {
+        return SubscribeRequest::default();
+    }
+    SubscribeRequest {
+        accounts: HashMap::from_iter(vec![(
+            generate_random_string(20),
+            SubscribeRequestFilterAccounts {
+                account: accounts,
+                ..Default::default()
+            },
+        )]),
+        commitment: Some(CommitmentLevel::Confirmed.into()),
+        ..Default::default()
+    }
+}
+
+/// parse_durable_nonce returns the nonce stored in a nonce account, None if the account isn't an initialized
+/// nonce account
+pub fn parse_durable_nonce(data: &[u8]) -> Option<String> {
+    match bincode::deserialize::<Versions>(data).ok()?.state() {
+        State::Initialized(data) => Some(data.blockhash().to_string()),
+        State::Uninitialized => None,
+    }
+}
+
 fn get_slot_subscribe_request() -> SubscribeRequest {
     SubscribeRequest {
         slots: HashMap::from_iter(vec![(
@@ -237,6 +524,11 @@ fn get_slot_subscribe_request() -> SubscribeRequest {
                 filter_by_commitment: Some(true),
             },
         )]),
+        // processed blockhashes for blockhash validation
+        blocks_meta: HashMap::from_iter(vec![(
+            generate_random_string(20).to_string(),
+            SubscribeRequestFilterBlocksMeta {},
+        )]),
         ..Default::default()
     }
 }
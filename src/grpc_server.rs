@@ -0,0 +1,324 @@
+use std::{collections::HashMap, pin::Pin, sync::Arc};
+
+use cadence_macros::statsd_count;
+use futures::{
+    channel::mpsc, future::BoxFuture, stream::FuturesOrdered, FutureExt, SinkExt, Stream, StreamExt,
+};
+use jsonrpsee::types::{error::INVALID_PARAMS_CODE, ErrorObjectOwned};
+use solana_rpc_client_api::{
+    config::RpcSendTransactionConfig,
+    custom_error::{
+        JSON_RPC_SERVER_ERROR_MIN_CONTEXT_SLOT_NOT_REACHED, JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY,
+        JSON_RPC_SERVER_ERROR_SEND_TRANSACTION_PREFLIGHT_FAILURE,
+        JSON_RPC_SERVER_ERROR_TRANSACTION_SIGNATURE_VERIFICATION_FAILURE,
+    },
+};
+use solana_sdk::commitment_config::CommitmentLevel as SolanaCommitmentLevel;
+use solana_transaction_status::UiTransactionEncoding;
+use tokio::sync::broadcast::error::RecvError;
+use tonic::{async_trait, metadata::MetadataMap, Request, Response, Status, Streaming};
+use tracing::warn;
+
+use crate::{
+    auth::{with_client_id, ApiKeys, API_KEY_HEADER},
+    errors::{
+        AtlasTxnSenderError, BLOCKHASH_EXPIRED_CODE, BLOCKHASH_NOT_FOUND_CODE, DECODE_ERROR_CODE,
+        NO_LEADERS_AVAILABLE_CODE, POLICY_REJECTED_CODE, RATE_LIMITED_CODE, SENDING_PAUSED_CODE,
+        UNSUPPORTED_CONFIG_CODE, UPSTREAM_RPC_ERROR_CODE,
+    },
+    request_id::{with_request_id, REQUEST_ID_HEADER},
+    rpc_server::{AtlasTxnSenderImpl, SendTransactionConfig, SignatureStatus},
+    transaction_store::{TransactionStatus, TransactionStore},
+};
+
+use self::proto::{
+    send_transaction_request::Transaction, transaction_sender_server::TransactionSender,
+    transaction_update::Update, Accepted, CommitmentLevel, Error, LandingStatus,
+    SendTransactionRequest, SendTransactionResponse, TransactionEncoding, TransactionUpdate,
+};
+
+pub mod proto {
+    tonic::include_proto!("atlas_txn_sender");
+}
+
+// updates buffered per stream before we stop reading new requests from it
+const STREAM_BUFFER_SIZE: usize = 1024;
+// sends in progress per stream before we stop reading new requests from it
+const MAX_PENDING_SENDS: usize = 64;
+
+/// GrpcTransactionSender serves the TransactionSender gRPC service with the same decoding, validation
+/// and sending as the JSON-RPC server
+#[derive(Clone)]
+pub struct GrpcTransactionSender {
+    atlas_txn_sender: AtlasTxnSenderImpl,
+    transaction_store: Arc<dyn TransactionStore>,
+    api_keys: ApiKeys,
+}
+
+impl GrpcTransactionSender {
+    pub fn new(
+        atlas_txn_sender: AtlasTxnSenderImpl,
+        transaction_store: Arc<dyn TransactionStore>,
+        api_keys: ApiKeys,
+    ) -> Self {
+        Self {
+            atlas_txn_sender,
+            transaction_store,
+            api_keys,
+        }
+    }
+
+    /// authenticate returns the client for the api key in the request metadata, None if auth is disabled
+    fn authenticate(&self, metadata: &MetadataMap) -> Result<Option<String>, Status> {
+        if !self.api_keys.is_enabled() {
+            return Ok(None);
+        }
+        metadata
+            .get(API_KEY_HEADER)
+            .and_then(|key| key.to_str().ok())
+            .and_then(|key| self.api_keys.client_id(key))
+            .map(|client_id| Some(client_id.to_string()))
+            .ok_or_else(|| {
+                statsd_count!("unauthorized_request", 1);
+                Status::unauthenticated("invalid api key")
+            })
+    }
+
+    async fn send(&self, request: SendTransactionRequest) -> Result<String, ErrorObjectOwned> {
+        let mut params = send_transaction_config(&request);
+        match request.transaction {
+            Some(Transaction::WireTransaction(wire_transaction)) => {
+                self.atlas_txn_sender
+                    .send_wire_transaction(wire_transaction, params)
+                    .await
+            }
+            Some(Transaction::EncodedTransaction(encoded_transaction)) => {
+                params.config.encoding = Some(match request.encoding() {
+                    TransactionEncoding::Base58 => UiTransactionEncoding::Base58,
+                    TransactionEncoding::Base64 => UiTransactionEncoding::Base64,
+                });
+                self.atlas_txn_sender
+                    .send_encoded_transaction(encoded_transaction, params)
+                    .await
+            }
+            None => Err(AtlasTxnSenderError::InvalidParams {
+                reason: "transaction is required".to_string(),
+            }
+            .into()),
+        }
+    }
+
+    /// request_id returns the `x-request-id` metadata of the request, if set
+    fn request_id(metadata: &MetadataMap) -> Option<String> {
+        metadata
+            .get(REQUEST_ID_HEADER)
+            .and_then(|request_id| request_id.to_str().ok())
+            .map(|request_id| request_id.to_string())
+    }
+
+    /// take_finished returns a status update for each request that sent a watched signature once the sender is
+    /// done with it
+    fn take_finished(
+        &self,
+        watching: &mut HashMap<String, Vec<u64>>,
+        signature: &str,
+    ) -> Vec<TransactionUpdate> {
+        if !watching.contains_key(signature) {
+            return vec![];
+        }
+        let Some(status) = self
+            .atlas_txn_sender
+            .get_signature_status(signature)
+            .filter(|status| status.landing_status.is_finished())
+        else {
+            return vec![];
+        };
+        watching
+            .remove(signature)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|id| status_update(id, signature.to_string(), status.clone()))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl TransactionSender for GrpcTransactionSender {
+    type SendTransactionStreamStream =
+        Pin<Box<dyn Stream<Item = Result<TransactionUpdate, Status>> + Send + 'static>>;
+
+    async fn send_transaction(
+        &self,
+        request: Request<SendTransactionRequest>,
+    ) -> Result<Response<SendTransactionResponse>, Status> {
+        statsd_count!("grpc_send_transaction", 1);
+        let client_id = self.authenticate(request.metadata())?;
+        let request_id = Self::request_id(request.metadata());
+        let signature = with_client_id(
+            client_id,
+            with_request_id(request_id, self.send(request.into_inner())),
+        )
+        .await
+        .map_err(|e| to_status(&e))?;
+        Ok(Response::new(SendTransactionResponse { signature }))
+    }
+
+    async fn send_transaction_stream(
+        &self,
+        request: Request<Streaming<SendTransactionRequest>>,
+    ) -> Result<Response<Self::SendTransactionStreamStream>, Status> {
+        statsd_count!("grpc_send_transaction_stream", 1);
+        let client_id = self.authenticate(request.metadata())?;
+        // every transaction on the stream shares the request id when one is set
+        let request_id = Self::request_id(request.metadata());
+        let mut requests = request.into_inner();
+        // subscribe before sending anything so no outcome is missed
+        let mut outcomes = self.transaction_store.subscribe_outcomes();
+        let (mut updates_sender, updates_receiver) = mpsc::channel(STREAM_BUFFER_SIZE);
+        let sender = self.clone();
+        tokio::spawn(async move {
+            // signature -> ids of the requests we still owe a status update, a signature can be sent more than once
+            let mut watching: HashMap<String, Vec<u64>> = HashMap::new();
+            // sends run concurrently so a slow one doesn't hold up the rest, they're answered in request order
+            let mut pending_sends: FuturesOrdered<BoxFuture<'static, (u64, Result<String, ErrorObjectOwned>)>> =
+                FuturesOrdered::new();
+            let mut requests_done = false;
+            while !requests_done || !pending_sends.is_empty() || !watching.is_empty() {
+                let mut updates = vec![];
+                tokio::select! {
+                    request = requests.next(), if !requests_done && pending_sends.len() < MAX_PENDING_SENDS => match request {
+                        Some(Ok(request)) => {
+                            let id = request.id;
+                            let sender = sender.clone();
+                            let client_id = client_id.clone();
+                            let request_id = request_id.clone();
+                            pending_sends.push_back(async move {
+                                let send = with_request_id(request_id, sender.send(request));
+                                (id, with_client_id(client_id, send).await)
+                            }.boxed());
+                        }
+                        Some(Err(e)) => {
+                            warn!("error reading gRPC transaction stream: {}", e);
+                            requests_done = true;
+                        }
+                        None => requests_done = true,
+                    },
+                    Some((id, sent)) = pending_sends.next(), if !pending_sends.is_empty() => match sent {
+                        Ok(signature) => {
+                            updates.push(TransactionUpdate {
+                                id,
+                                signature: signature.clone(),
+                                update: Some(Update::Accepted(Accepted {})),
+                            });
+                            watching.entry(signature.clone()).or_default().push(id);
+                            // the outcome could have been recorded before we started watching
+                            updates.extend(sender.take_finished(&mut watching, &signature));
+                        }
+                        Err(e) => updates.push(error_update(id, &e)),
+                    },
+                    outcome = outcomes.recv() => match outcome {
+                        Ok((signature, _)) => {
+                            updates.extend(sender.take_finished(&mut watching, &signature));
+                        }
+                        Err(RecvError::Lagged(_)) => {
+                            let signatures: Vec<String> = watching.keys().cloned().collect();
+                            for signature in signatures {
+                                updates.extend(sender.take_finished(&mut watching, &signature));
+                            }
+                        }
+                        Err(RecvError::Closed) => return,
+                    },
+                }
+                for update in updates {
+                    if updates_sender.send(Ok(update)).await.is_err() {
+                        // client went away
+                        return;
+                    }
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(updates_receiver)))
+    }
+}
+
+fn send_transaction_config(request: &SendTransactionRequest) -> SendTransactionConfig {
+    let config = request.config.clone().unwrap_or_default();
+    let preflight_commitment = config.preflight_commitment.map(|_| match config.preflight_commitment() {
+        CommitmentLevel::Finalized => SolanaCommitmentLevel::Finalized,
+        CommitmentLevel::Confirmed => SolanaCommitmentLevel::Confirmed,
+        CommitmentLevel::Processed => SolanaCommitmentLevel::Processed,
+    });
+    SendTransactionConfig {
+        config: RpcSendTransactionConfig {
+            skip_preflight: config.skip_preflight,
+            preflight_commitment,
+            encoding: None,
+            max_retries: config.max_retries.map(|max_retries| max_retries as usize),
+            min_context_slot: config.min_context_slot,
+        },
+        // the request id comes from the request metadata
+        request_id: None,
+        deadline: config.deadline,
+    }
+}
+
+fn to_status(e: &ErrorObjectOwned) -> Status {
+    let code = e.code();
+    if code == INVALID_PARAMS_CODE
+        || code == DECODE_ERROR_CODE
+        || code == UNSUPPORTED_CONFIG_CODE
+        || code == JSON_RPC_SERVER_ERROR_TRANSACTION_SIGNATURE_VERIFICATION_FAILURE as i32
+    {
+        Status::invalid_argument(e.message())
+    } else if code == JSON_RPC_SERVER_ERROR_SEND_TRANSACTION_PREFLIGHT_FAILURE as i32
+        || code == BLOCKHASH_NOT_FOUND_CODE
+        || code == BLOCKHASH_EXPIRED_CODE
+    {
+        Status::failed_precondition(e.message())
+    } else if code == POLICY_REJECTED_CODE {
+        Status::permission_denied(e.message())
+    } else if code == RATE_LIMITED_CODE {
+        Status::resource_exhausted(e.message())
+    } else if code == NO_LEADERS_AVAILABLE_CODE
+        || code == UPSTREAM_RPC_ERROR_CODE
+        || code == SENDING_PAUSED_CODE
+        || code == JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY as i32
+        || code == JSON_RPC_SERVER_ERROR_MIN_CONTEXT_SLOT_NOT_REACHED as i32
+    {
+        Status::unavailable(e.message())
+    } else {
+        Status::unknown(e.message())
+    }
+}
+
+fn error_update(id: u64, e: &ErrorObjectOwned) -> TransactionUpdate {
+    TransactionUpdate {
+        id,
+        signature: String::new(),
+        update: Some(Update::Error(Error {
+            code: e.code(),
+            message: e.message().to_string(),
+            data: e.data().map(|data| data.get().to_string()).unwrap_or_default(),
+        })),
+    }
+}
+
+fn status_update(id: u64, signature: String, status: SignatureStatus) -> TransactionUpdate {
+    let landing_status = match status.landing_status {
+        TransactionStatus::Pending => LandingStatus::Pending,
+        TransactionStatus::Retrying => LandingStatus::Retrying,
+        TransactionStatus::Landed => LandingStatus::Landed,
+        TransactionStatus::Expired => LandingStatus::Expired,
+        TransactionStatus::Cancelled => LandingStatus::Cancelled,
+        TransactionStatus::Dropped => LandingStatus::Dropped,
+    };
+    TransactionUpdate {
+        id,
+        signature,
+        update: Some(Update::Status(proto::Status {
+            landing_status: landing_status as i32,
+            slot: status.slot,
+            err: status.err.map(|err| err.to_string()),
+        })),
+    }
+}
@@ -0,0 +1,91 @@
+use std::{
+    error::Error,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use hyper::{
+    header::{CONTENT_TYPE, HeaderValue},
+    Body, Method, Request, Response, StatusCode,
+};
+use tower::{Layer, Service};
+
+use crate::rpc_server::AtlasTxnSenderImpl;
+
+/// HealthLayer serves `GET <path>` with the health report, 200 when the sender is ready and 503 otherwise
+/// so load balancers stop routing to it, everything else is passed through to the json rpc server
+#[derive(Clone)]
+pub struct HealthLayer {
+    path: Arc<str>,
+    atlas_txn_sender: AtlasTxnSenderImpl,
+}
+
+impl HealthLayer {
+    pub fn new(path: &str, atlas_txn_sender: AtlasTxnSenderImpl) -> Self {
+        Self {
+            path: Arc::from(path),
+            atlas_txn_sender,
+        }
+    }
+}
+
+impl<S> Layer<S> for HealthLayer {
+    type Service = Health<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Health {
+            inner,
+            path: self.path.clone(),
+            atlas_txn_sender: self.atlas_txn_sender.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Health<S> {
+    inner: S,
+    path: Arc<str>,
+    atlas_txn_sender: AtlasTxnSenderImpl,
+}
+
+impl<S> Service<Request<Body>> for Health<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+    S::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = Box<dyn Error + Send + Sync + 'static>;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        if req.uri().path() != self.path.as_ref() || req.method() != Method::GET {
+            let fut = self.inner.call(req);
+            return Box::pin(async move { fut.await.map_err(Into::into) });
+        }
+        let atlas_txn_sender = self.atlas_txn_sender.clone();
+        Box::pin(async move {
+            let health = atlas_txn_sender.get_health().await;
+            let status = if health.ready {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            };
+            let response = Response::builder()
+                .status(status)
+                .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+                .body(Body::from(
+                    serde_json::to_string(&health).expect("health report serializes"),
+                ))
+                .expect("valid response");
+            Ok::<_, Self::Error>(response)
+        })
+    }
+}
@@ -1,8 +1,9 @@
 use core::panic;
 use std::{
     collections::HashMap,
+    net::SocketAddr,
     sync::{
-        atomic::{AtomicU64, Ordering},
+        atomic::{AtomicU64, AtomicUsize, Ordering},
         Arc,
     },
     time::{Duration, Instant},
@@ -10,17 +11,38 @@ use std::{
 
 use cadence_macros::statsd_time;
 use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
 use solana_client::rpc_client::RpcClient;
 use solana_rpc_client_api::response::RpcContactInfo;
-use solana_sdk::slot_history::Slot;
+use solana_sdk::{pubkey::Pubkey, slot_history::Slot};
 use tokio::time::sleep;
 use tracing::{debug, error};
 
-use crate::{errors::AtlasTxnSenderError, solana_rpc::SolanaRpc};
+use crate::{errors::AtlasTxnSenderError, solana_rpc::SolanaRpc, utils::now_unix_millis};
+
+/// UpcomingLeader is the leader scheduled for a slot and what we know about how to reach it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpcomingLeader {
+    pub slot: Slot,
+    pub identity: String,
+    pub tpu_quic: Option<SocketAddr>,
+    // false when the leader wasn't found in getClusterNodes
+    pub has_contact_info: bool,
+    // true when transactions are currently sent to this leader
+    pub targeted: bool,
+}
 
 pub trait LeaderTracker: Send + Sync {
     /// get_leaders returns the next slot leaders in order
     fn get_leaders(&self) -> Vec<RpcContactInfo>;
+    /// get_upcoming_leaders returns the schedule for the next num_slots slots, including leaders we have no contact info for
+    fn get_upcoming_leaders(&self, num_slots: u64) -> Vec<UpcomingLeader>;
+    /// get_leader_schedule_age_ms returns how long ago the leader schedule was last refreshed, None if it never was
+    fn get_leader_schedule_age_ms(&self) -> Option<u64>;
+    fn get_num_leaders(&self) -> usize;
+    /// set_num_leaders changes how many upcoming leaders transactions are sent to
+    fn set_num_leaders(&self, num_leaders: usize);
 }
 
 #[derive(Clone)]
@@ -29,7 +51,11 @@ pub struct LeaderTrackerImpl {
     solana_rpc: Arc<dyn SolanaRpc>,
     cur_slot: Arc<AtomicU64>,
     cur_leaders: Arc<DashMap<Slot, RpcContactInfo>>,
-    num_leaders: usize,
+    cur_slot_leaders: Arc<DashMap<Slot, Pubkey>>,
+    // unix timestamp in milliseconds of the last successful poll, 0 until the first one
+    leader_schedule_updated_at: Arc<AtomicU64>,
+    // changed at runtime through the admin api
+    num_leaders: Arc<AtomicUsize>,
 }
 
 impl LeaderTrackerImpl {
@@ -43,7 +69,9 @@ impl LeaderTrackerImpl {
             solana_rpc,
             cur_slot: Arc::new(AtomicU64::new(0)),
             cur_leaders: Arc::new(DashMap::new()),
-            num_leaders,
+            cur_slot_leaders: Arc::new(DashMap::new()),
+            leader_schedule_updated_at: Arc::new(AtomicU64::new(0)),
+            num_leaders: Arc::new(AtomicUsize::new(num_leaders)),
         };
         leader_tracker.poll_slot();
         leader_tracker.poll_slot_leaders();
@@ -95,12 +123,16 @@ impl LeaderTrackerImpl {
         // polling 1000 slots ahead is more than enough
         let slot_leaders = self.rpc_client.get_slot_leaders(next_slot, 1000);
         if let Err(e) = slot_leaders {
-            return Err(format!("Error getting slot leaders: {}", e).into());
+            return Err(AtlasTxnSenderError::UpstreamRpc {
+                reason: format!("Error getting slot leaders: {}", e),
+            });
         }
         let slot_leaders = slot_leaders.unwrap();
         let new_cluster_nodes = self.rpc_client.get_cluster_nodes();
         if let Err(e) = new_cluster_nodes {
-            return Err(format!("Error getting cluster nodes: {}", e).into());
+            return Err(AtlasTxnSenderError::UpstreamRpc {
+                reason: format!("Error getting cluster nodes: {}", e),
+            });
         }
         let new_cluster_nodes = new_cluster_nodes.unwrap();
         let mut cluster_node_map = HashMap::new();
@@ -108,6 +140,7 @@ impl LeaderTrackerImpl {
             cluster_node_map.insert(node.pubkey.clone(), node);
         }
         for (i, leader) in slot_leaders.iter().enumerate() {
+            self.cur_slot_leaders.insert(next_slot + i as u64, *leader);
             let contact_info = cluster_node_map.get(&leader.to_string());
             if let Some(contact_info) = contact_info {
                 self.cur_leaders
@@ -117,6 +150,8 @@ impl LeaderTrackerImpl {
             }
         }
         self.clean_up_slot_leaders();
+        self.leader_schedule_updated_at
+            .store(now_unix_millis(), Ordering::Relaxed);
         Ok(())
     }
 
@@ -131,6 +166,7 @@ impl LeaderTrackerImpl {
         for slot in slots_to_remove {
             self.cur_leaders.remove(&slot);
         }
+        self.cur_slot_leaders.retain(|slot, _| *slot >= cur_slot);
     }
 }
 
@@ -138,7 +174,8 @@ impl LeaderTracker for LeaderTrackerImpl {
     fn get_leaders(&self) -> Vec<RpcContactInfo> {
         let mut leaders = vec![];
         let cur_slot = self.cur_slot.load(Ordering::Relaxed);
-        for slot in cur_slot..cur_slot + self.num_leaders as u64 {
+        let num_leaders = self.get_num_leaders() as u64;
+        for slot in cur_slot..cur_slot + num_leaders {
             let leader = self.cur_leaders.get(&slot);
             if let Some(leader) = leader {
                 leaders.push(leader.value().clone());
@@ -146,4 +183,41 @@ impl LeaderTracker for LeaderTrackerImpl {
         }
         leaders
     }
+
+    fn get_upcoming_leaders(&self, num_slots: u64) -> Vec<UpcomingLeader> {
+        let mut upcoming_leaders = vec![];
+        let cur_slot = self.cur_slot.load(Ordering::Relaxed);
+        let num_leaders = self.get_num_leaders() as u64;
+        for slot in cur_slot..cur_slot + num_slots {
+            let identity = self.cur_slot_leaders.get(&slot);
+            if identity.is_none() {
+                continue;
+            }
+            let contact_info = self.cur_leaders.get(&slot);
+            upcoming_leaders.push(UpcomingLeader {
+                slot,
+                identity: identity.unwrap().to_string(),
+                tpu_quic: contact_info.as_ref().and_then(|c| c.tpu_quic),
+                has_contact_info: contact_info.is_some(),
+                targeted: slot < cur_slot + num_leaders,
+            });
+        }
+        upcoming_leaders
+    }
+
+    fn get_leader_schedule_age_ms(&self) -> Option<u64> {
+        let updated_at = self.leader_schedule_updated_at.load(Ordering::Relaxed);
+        if updated_at == 0 {
+            return None;
+        }
+        Some(now_unix_millis().saturating_sub(updated_at))
+    }
+
+    fn get_num_leaders(&self) -> usize {
+        self.num_leaders.load(Ordering::Relaxed)
+    }
+
+    fn set_num_leaders(&self, num_leaders: usize) {
+        self.num_leaders.store(num_leaders, Ordering::Relaxed);
+    }
 }
@@ -1,6 +1,15 @@
+mod admin_rpc_server;
+mod auth;
 mod errors;
 mod grpc_geyser;
+mod grpc_server;
+mod health_layer;
 mod leader_tracker;
+mod program_policy;
+mod rate_limiter;
+mod raw_transaction_layer;
+mod request_id;
+mod rpc_passthrough_layer;
 mod rpc_server;
 mod solana_rpc;
 mod transaction_store;
@@ -14,17 +23,30 @@ use std::{
     sync::Arc,
 };
 
+use admin_rpc_server::{AtlasTxnSenderAdminImpl, AtlasTxnSenderAdminServer};
+use auth::{ApiKeys, ClientIdentityLayer};
 use cadence::{BufferedUdpMetricSink, QueuingMetricSink, StatsdClient};
 use cadence_macros::set_global_default;
 use figment::{providers::Env, Figment};
 use grpc_geyser::GrpcGeyserImpl;
-use jsonrpsee::server::{middleware::ProxyGetRequestLayer, ServerBuilder};
+use grpc_server::{proto::transaction_sender_server::TransactionSenderServer, GrpcTransactionSender};
+use health_layer::HealthLayer;
+use jsonrpsee::server::ServerBuilder;
 use leader_tracker::LeaderTrackerImpl;
+use program_policy::ProgramPolicyImpl;
+use rate_limiter::RateLimiterImpl;
+use raw_transaction_layer::RawTransactionLayer;
+use request_id::RequestIdLayer;
+use rpc_passthrough_layer::RpcPassthroughLayer;
 use rpc_server::{AtlasTxnSenderImpl, AtlasTxnSenderServer};
 use serde::Deserialize;
-use solana_client::{connection_cache::ConnectionCache, rpc_client::RpcClient};
+use solana_client::{
+    connection_cache::ConnectionCache, nonblocking::rpc_client::RpcClient as NonblockingRpcClient,
+    rpc_client::RpcClient,
+};
 use solana_sdk::signature::{read_keypair_file, Keypair};
 use tokio::sync::RwLock;
+use tower_http::validate_request::ValidateRequestHeaderLayer;
 use tracing::{error, info};
 use transaction_store::TransactionStoreImpl;
 use txn_sender::TxnSenderImpl;
@@ -40,6 +62,13 @@ struct AtlasTxnSenderEnv {
     x_token: Option<String>,
     num_leaders: Option<usize>,
     txn_sender_threads: Option<usize>,
+    admin_host: Option<String>,
+    admin_port: Option<u16>,
+    grpc_server_port: Option<u16>,
+    api_keys: Option<String>,
+    client_limits: Option<String>,
+    program_policies: Option<String>,
+    rpc_passthrough_methods: Option<String>,
 }
 
 // Defualt on RPC is 4
@@ -57,19 +86,13 @@ async fn main() -> anyhow::Result<()> {
         .json()
         .init();
     new_metrics_client();
+    let api_keys = ApiKeys::parse(&env.api_keys.clone().unwrap_or_default())?;
+    if !api_keys.is_enabled() {
+        info!("API_KEYS not set, requests are not authenticated");
+    }
+    let client_limits = RateLimiterImpl::parse_limits(&env.client_limits.clone().unwrap_or_default())?;
+    let program_rules = ProgramPolicyImpl::parse_rules(&env.program_policies.clone().unwrap_or_default())?;
 
-    let service_builder = tower::ServiceBuilder::new()
-        // Proxy `GET /health` requests to internal `health` method.
-        .layer(ProxyGetRequestLayer::new("/health", "health")?);
-    let port = env.port.unwrap_or(4040);
-
-    let server = ServerBuilder::default()
-        .set_middleware(service_builder)
-        .max_request_body_size(15_000_000)
-        .max_connections(1_000_000)
-        .build(format!("0.0.0.0:{}", port))
-        .await
-        .unwrap();
     let tpu_connection_pool_size = env
         .tpu_connection_pool_size
         .unwrap_or(DEFAULT_TPU_CONNECTION_POOL_SIZE);
@@ -101,22 +124,107 @@ async fn main() -> anyhow::Result<()> {
     ));
     let transaction_store = Arc::new(TransactionStoreImpl::new());
     let solana_rpc = Arc::new(GrpcGeyserImpl::new(client));
-    let rpc_client = Arc::new(RpcClient::new(env.rpc_url.unwrap()));
+    let rpc_url = env.rpc_url.unwrap();
+    let rpc_client = Arc::new(RpcClient::new(rpc_url.clone()));
+    // used for preflight simulations and fetching address lookup tables
+    let nonblocking_rpc_client = Arc::new(NonblockingRpcClient::new(rpc_url.clone()));
     let num_leaders = env.num_leaders.unwrap_or(4);
     let leader_tracker = Arc::new(LeaderTrackerImpl::new(
         rpc_client,
         solana_rpc.clone(),
         num_leaders,
     ));
+    let rate_limiter = Arc::new(RateLimiterImpl::new(client_limits));
+    let program_policy = Arc::new(ProgramPolicyImpl::new(
+        program_rules,
+        nonblocking_rpc_client.clone(),
+    ));
     let txn_sender = Arc::new(TxnSenderImpl::new(
-        leader_tracker,
-        transaction_store,
+        leader_tracker.clone(),
+        transaction_store.clone(),
         connection_cache,
-        solana_rpc,
+        solana_rpc.clone(),
+        rate_limiter.clone(),
         env.txn_sender_threads.unwrap_or(4),
     ));
-    let atlas_txn_sender = AtlasTxnSenderImpl::new(txn_sender);
-    let handle = server.start(atlas_txn_sender.into_rpc());
+    let atlas_txn_sender_admin = AtlasTxnSenderAdminImpl::new(
+        transaction_store.clone(),
+        txn_sender.clone(),
+        leader_tracker.clone(),
+    );
+    let atlas_txn_sender = AtlasTxnSenderImpl::new(
+        txn_sender,
+        transaction_store.clone(),
+        solana_rpc,
+        nonblocking_rpc_client,
+        leader_tracker,
+        rate_limiter,
+        program_policy,
+        api_keys.clone(),
+    );
+    if let Some(grpc_server_port) = env.grpc_server_port {
+        let grpc_transaction_sender = GrpcTransactionSender::new(
+            atlas_txn_sender.clone(),
+            transaction_store,
+            api_keys.clone(),
+        );
+        tokio::spawn(async move {
+            let grpc_server = tonic::transport::Server::builder()
+                .add_service(TransactionSenderServer::new(grpc_transaction_sender))
+                .serve(format!("0.0.0.0:{}", grpc_server_port).parse().unwrap())
+                .await;
+            if let Err(e) = grpc_server {
+                error!("gRPC server exited: {}", e);
+            }
+        });
+    }
+    let rpc_module = atlas_txn_sender.clone().into_rpc();
+    let local_methods = rpc_module
+        .method_names()
+        .map(|method| method.to_string())
+        .collect();
+    let passthrough_methods =
+        RpcPassthroughLayer::parse_methods(env.rpc_passthrough_methods.as_deref());
+    let service_builder = tower::ServiceBuilder::new()
+        // Reject requests without a valid api key, and tag the rest with their client.
+        .layer(ValidateRequestHeaderLayer::custom(api_keys))
+        .layer(ClientIdentityLayer)
+        // Tag requests with the client's `x-request-id` so their transactions can be traced in the logs.
+        .layer(RequestIdLayer)
+        // Serve `GET /health` with the health report, 503 when the sender isn't ready.
+        .layer(HealthLayer::new("/health", atlas_txn_sender.clone()))
+        // Serve `POST /sendRawTransaction` with a bincode serialized transaction body.
+        .layer(RawTransactionLayer::new(
+            "/sendRawTransaction",
+            atlas_txn_sender.clone(),
+        ))
+        // Forward allowed methods we don't implement to `RPC_URL`.
+        .layer(RpcPassthroughLayer::new(
+            &rpc_url,
+            local_methods,
+            passthrough_methods,
+        ));
+    let port = env.port.unwrap_or(4040);
+
+    let server = ServerBuilder::default()
+        .set_middleware(service_builder)
+        .max_request_body_size(15_000_000)
+        .max_connections(1_000_000)
+        .build(format!("0.0.0.0:{}", port))
+        .await
+        .unwrap();
+    let handle = server.start(rpc_module);
+    // Admin methods are served on their own listener, bound to localhost unless ADMIN_HOST is set.
+    let admin_server = ServerBuilder::default()
+        .build(format!(
+            "{}:{}",
+            env.admin_host.unwrap_or("127.0.0.1".to_string()),
+            env.admin_port.unwrap_or(4041)
+        ))
+        .await
+        .unwrap();
+    let admin_handle = admin_server.start(atlas_txn_sender_admin.into_rpc());
+    tokio::spawn(admin_handle.stopped());
     handle.stopped().await;
     Ok(())
 }
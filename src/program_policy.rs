@@ -0,0 +1,238 @@
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use cadence_macros::{statsd_count, statsd_time};
+use dashmap::DashMap;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    address_lookup_table::state::AddressLookupTable, pubkey::Pubkey,
+    transaction::VersionedTransaction,
+};
+use tonic::async_trait;
+
+use crate::errors::AtlasTxnSenderError;
+
+// rules under this client apply to every client without rules of its own
+pub const DEFAULT_POLICY_CLIENT: &str = "*";
+// lookup tables only grow while they're active, so a cached table is refetched when an index is past its end
+const LOOKUP_TABLE_CACHE_TTL: Duration = Duration::from_secs(600);
+// tables that don't exist or are too short are refetched at most this often, so transactions referencing made up
+// tables can't flood the rpc
+const LOOKUP_TABLE_REFETCH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// ProgramRules are the programs a client's transactions may use, unset rules aren't enforced
+#[derive(Clone, Debug, Default)]
+pub struct ProgramRules {
+    // every instruction has to call one of these programs
+    pub allowed: Option<HashSet<Pubkey>>,
+    // none of the transaction's accounts, including ones loaded from lookup tables, can be one of these programs,
+    // so they can't be called through cpi either
+    pub denied: HashSet<Pubkey>,
+}
+
+#[async_trait]
+pub trait ProgramPolicy: Send + Sync {
+    /// check rejects the transaction if it uses a program the client's rules don't allow
+    async fn check(
+        &self,
+        client: &str,
+        transaction: &VersionedTransaction,
+    ) -> Result<(), AtlasTxnSenderError>;
+}
+
+pub struct ProgramPolicyImpl {
+    rules: Arc<HashMap<String, ProgramRules>>,
+    rpc_client: Arc<RpcClient>,
+    // lookup table -> its addresses, None if it isn't a lookup table, and when they were fetched
+    lookup_tables: DashMap<Pubkey, (Option<Vec<Pubkey>>, Instant)>,
+}
+
+impl ProgramPolicyImpl {
+    pub fn new(rules: HashMap<String, ProgramRules>, rpc_client: Arc<RpcClient>) -> Self {
+        Self {
+            rules: Arc::new(rules),
+            rpc_client,
+            lookup_tables: DashMap::new(),
+        }
+    }
+
+    /// parses rules formatted as `client:allow|deny:program;program`, comma separated, a client can have an
+    /// allow and a deny entry and `*` sets the rules for every other client
+    pub fn parse_rules(program_policies: &str) -> anyhow::Result<HashMap<String, ProgramRules>> {
+        let mut rules: HashMap<String, ProgramRules> = HashMap::new();
+        for entry in program_policies
+            .split(',')
+            .filter(|entry| !entry.trim().is_empty())
+        {
+            let fields: Vec<&str> = entry.trim().split(':').collect();
+            if fields.len() != 3 {
+                return Err(anyhow::anyhow!(
+                    "program policy entry must be client:allow|deny:programs, got {entry}"
+                ));
+            }
+            let programs = fields[2]
+                .split(';')
+                .filter(|program| !program.trim().is_empty())
+                .map(|program| {
+                    Pubkey::from_str(program.trim()).map_err(|_| {
+                        anyhow::anyhow!("invalid program {program} in program policy entry {entry}")
+                    })
+                })
+                .collect::<anyhow::Result<HashSet<Pubkey>>>()?;
+            let client_rules = rules.entry(fields[0].to_string()).or_default();
+            match fields[1] {
+                "allow" => client_rules
+                    .allowed
+                    .get_or_insert_with(HashSet::new)
+                    .extend(programs),
+                "deny" => client_rules.denied.extend(programs),
+                mode => {
+                    return Err(anyhow::anyhow!(
+                        "program policy mode must be allow or deny, got {mode} in {entry}"
+                    ))
+                }
+            }
+        }
+        Ok(rules)
+    }
+
+    fn get_rules(&self, client: &str) -> Option<&ProgramRules> {
+        self.rules
+            .get(client)
+            .or_else(|| self.rules.get(DEFAULT_POLICY_CLIENT))
+    }
+
+    /// get_loaded_addresses returns every address the transaction loads from lookup tables, fetching the
+    /// tables that aren't cached or don't have the indexes it uses
+    async fn get_loaded_addresses(
+        &self,
+        transaction: &VersionedTransaction,
+    ) -> Result<Vec<Pubkey>, AtlasTxnSenderError> {
+        let Some(lookups) = transaction.message.address_table_lookups() else {
+            return Ok(vec![]);
+        };
+        let missing: Vec<Pubkey> = lookups
+            .iter()
+            .filter(|lookup| {
+                let max_index = lookup
+                    .writable_indexes
+                    .iter()
+                    .chain(&lookup.readonly_indexes)
+                    .max()
+                    .copied()
+                    .unwrap_or_default();
+                self.lookup_tables
+                    .get(&lookup.account_key)
+                    .map_or(true, |table| {
+                        let age = table.1.elapsed();
+                        let incomplete = table
+                            .0
+                            .as_ref()
+                            .map_or(true, |addresses| max_index as usize >= addresses.len());
+                        age >= LOOKUP_TABLE_CACHE_TTL
+                            || (incomplete && age >= LOOKUP_TABLE_REFETCH_INTERVAL)
+                    })
+            })
+            .map(|lookup| lookup.account_key)
+            .collect();
+        if !missing.is_empty() {
+            self.fetch_lookup_tables(&missing).await?;
+        }
+        let mut addresses = vec![];
+        for lookup in lookups {
+            let table = self.lookup_tables.get(&lookup.account_key);
+            let table_addresses = table
+                .as_ref()
+                .and_then(|table| table.0.as_ref())
+                .ok_or_else(|| {
+                    policy_rejected(format!(
+                        "address lookup table {} not found",
+                        lookup.account_key
+                    ))
+                })?;
+            for index in lookup.writable_indexes.iter().chain(&lookup.readonly_indexes) {
+                let address = table_addresses.get(*index as usize).ok_or_else(|| {
+                    policy_rejected(format!(
+                        "index {index} is out of bounds for address lookup table {}",
+                        lookup.account_key
+                    ))
+                })?;
+                addresses.push(*address);
+            }
+        }
+        Ok(addresses)
+    }
+
+    async fn fetch_lookup_tables(&self, lookup_tables: &[Pubkey]) -> Result<(), AtlasTxnSenderError> {
+        let start = Instant::now();
+        let accounts = self
+            .rpc_client
+            .get_multiple_accounts(lookup_tables)
+            .await
+            .map_err(|e| AtlasTxnSenderError::UpstreamRpc {
+                reason: format!("failed to fetch address lookup tables: {e}"),
+            })?;
+        statsd_time!("lookup_table_fetch_time", start.elapsed());
+        // tables nobody used since they expired are dropped
+        self.lookup_tables
+            .retain(|_, (_, fetched_at)| fetched_at.elapsed() < LOOKUP_TABLE_CACHE_TTL);
+        for (key, account) in lookup_tables.iter().zip(accounts) {
+            let addresses = account.and_then(|account| {
+                AddressLookupTable::deserialize(&account.data)
+                    .ok()
+                    .map(|table| table.addresses.to_vec())
+            });
+            self.lookup_tables.insert(*key, (addresses, Instant::now()));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ProgramPolicy for ProgramPolicyImpl {
+    async fn check(
+        &self,
+        client: &str,
+        transaction: &VersionedTransaction,
+    ) -> Result<(), AtlasTxnSenderError> {
+        let Some(rules) = self.get_rules(client) else {
+            return Ok(());
+        };
+        let static_account_keys = transaction.message.static_account_keys();
+        if let Some(allowed) = &rules.allowed {
+            // program ids are always static keys, they can't be loaded from lookup tables
+            for instruction in transaction.message.instructions() {
+                let program_id = static_account_keys
+                    .get(instruction.program_id_index as usize)
+                    .expect("program id index is sanitized");
+                if !allowed.contains(program_id) {
+                    statsd_count!("policy_rejected", 1, "client" => client, "rule" => "allow");
+                    return Err(policy_rejected(format!(
+                        "program {program_id} isn't allowed"
+                    )));
+                }
+            }
+        }
+        if rules.denied.is_empty() {
+            return Ok(());
+        }
+        let loaded_addresses = self.get_loaded_addresses(transaction).await?;
+        if let Some(program_id) = static_account_keys
+            .iter()
+            .chain(&loaded_addresses)
+            .find(|account| rules.denied.contains(*account))
+        {
+            statsd_count!("policy_rejected", 1, "client" => client, "rule" => "deny");
+            return Err(policy_rejected(format!("program {program_id} is denied")));
+        }
+        Ok(())
+    }
+}
+
+fn policy_rejected(reason: String) -> AtlasTxnSenderError {
+    AtlasTxnSenderError::PolicyRejected { reason }
+}
@@ -0,0 +1,155 @@
+use std::{collections::HashMap, sync::Arc, time::Instant};
+
+use cadence_macros::statsd_count;
+use dashmap::DashMap;
+
+use crate::errors::AtlasTxnSenderError;
+
+// limits under this client apply to every client without limits of its own
+pub const DEFAULT_LIMITS_CLIENT: &str = "*";
+
+/// ClientLimits caps how much of the sender a single client can use, unset limits aren't enforced
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ClientLimits {
+    // transactions accepted per second
+    pub max_tps: Option<u32>,
+    // transactions the sender is retrying or watching at once
+    pub max_in_flight: Option<usize>,
+    // retries of the client's transactions the retry loop sends within a 10 second window
+    pub retry_budget: Option<usize>,
+}
+
+pub trait RateLimiter: Send + Sync {
+    /// acquire takes up to `num_transactions` from the client's limits and returns how many can be sent,
+    /// errors if none can. Each one holds an in flight slot until it's released or refunded
+    fn acquire(&self, client: &str, num_transactions: usize) -> Result<usize, AtlasTxnSenderError>;
+    /// refund gives back everything acquire took for transactions that weren't sent after all
+    fn refund(&self, client: &str, num_transactions: usize);
+    /// release frees the in flight slot of a transaction the sender stopped working on
+    fn release(&self, client: &str);
+    fn retry_budget(&self, client: &str) -> Option<usize>;
+}
+
+/// ClientUsage is what a client has taken from its limits
+struct ClientUsage {
+    // tokens left and when they were last refilled
+    tokens: f64,
+    refilled_at: Instant,
+    in_flight: usize,
+}
+
+pub struct RateLimiterImpl {
+    limits: Arc<HashMap<String, ClientLimits>>,
+    usage: DashMap<String, ClientUsage>,
+}
+
+impl RateLimiterImpl {
+    pub fn new(limits: HashMap<String, ClientLimits>) -> Self {
+        Self {
+            limits: Arc::new(limits),
+            usage: DashMap::new(),
+        }
+    }
+
+    /// parses limits formatted as `client:max_tps:max_in_flight:retry_budget`, comma separated,
+    /// empty fields aren't enforced and `*` sets the limits for every other client
+    pub fn parse_limits(client_limits: &str) -> anyhow::Result<HashMap<String, ClientLimits>> {
+        let mut limits = HashMap::new();
+        for entry in client_limits
+            .split(',')
+            .filter(|entry| !entry.trim().is_empty())
+        {
+            let fields: Vec<&str> = entry.trim().split(':').collect();
+            if fields.len() != 4 {
+                return Err(anyhow::anyhow!(
+                    "client limits entry must be client:max_tps:max_in_flight:retry_budget, got {entry}"
+                ));
+            }
+            let client_limits = ClientLimits {
+                max_tps: parse_limit(fields[1], entry)?,
+                max_in_flight: parse_limit(fields[2], entry)?,
+                retry_budget: parse_limit(fields[3], entry)?,
+            };
+            limits.insert(fields[0].to_string(), client_limits);
+        }
+        Ok(limits)
+    }
+
+    fn get_limits(&self, client: &str) -> ClientLimits {
+        self.limits
+            .get(client)
+            .or_else(|| self.limits.get(DEFAULT_LIMITS_CLIENT))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+impl RateLimiter for RateLimiterImpl {
+    fn acquire(&self, client: &str, num_transactions: usize) -> Result<usize, AtlasTxnSenderError> {
+        let limits = self.get_limits(client);
+        let mut allowed = num_transactions;
+        // the entry is locked until we return, so concurrent requests can't take the same slots and tokens
+        let mut usage = self
+            .usage
+            .entry(client.to_string())
+            .or_insert_with(|| ClientUsage {
+                tokens: limits.max_tps.unwrap_or_default() as f64,
+                refilled_at: Instant::now(),
+                in_flight: 0,
+            });
+        if let Some(max_in_flight) = limits.max_in_flight {
+            let in_flight = usage.in_flight;
+            allowed = allowed.min(max_in_flight.saturating_sub(in_flight));
+            if allowed == 0 {
+                statsd_count!("rate_limited", 1, "client" => client, "limit" => "max_in_flight");
+                return Err(AtlasTxnSenderError::RateLimited {
+                    limit: "maxInFlight".to_string(),
+                    reason: format!("{in_flight} transactions in flight; max {max_in_flight}"),
+                });
+            }
+        }
+        if let Some(max_tps) = limits.max_tps {
+            let max_tps = max_tps as f64;
+            usage.tokens = (usage.tokens + usage.refilled_at.elapsed().as_secs_f64() * max_tps).min(max_tps);
+            usage.refilled_at = Instant::now();
+            allowed = allowed.min(usage.tokens as usize);
+            if allowed == 0 {
+                statsd_count!("rate_limited", 1, "client" => client, "limit" => "max_tps");
+                return Err(AtlasTxnSenderError::RateLimited {
+                    limit: "maxTps".to_string(),
+                    reason: format!("too many transactions per second; max {max_tps}"),
+                });
+            }
+            usage.tokens -= allowed as f64;
+        }
+        usage.in_flight += allowed;
+        Ok(allowed)
+    }
+    fn refund(&self, client: &str, num_transactions: usize) {
+        let max_tps = self.get_limits(client).max_tps;
+        if let Some(mut usage) = self.usage.get_mut(client) {
+            if let Some(max_tps) = max_tps {
+                usage.tokens = (usage.tokens + num_transactions as f64).min(max_tps as f64);
+            }
+            usage.in_flight = usage.in_flight.saturating_sub(num_transactions);
+        }
+    }
+    fn release(&self, client: &str) {
+        if let Some(mut usage) = self.usage.get_mut(client) {
+            usage.in_flight = usage.in_flight.saturating_sub(1);
+        }
+    }
+    fn retry_budget(&self, client: &str) -> Option<usize> {
+        self.get_limits(client).retry_budget
+    }
+}
+
+fn parse_limit<T: std::str::FromStr>(limit: &str, entry: &str) -> anyhow::Result<Option<T>> {
+    if limit.is_empty() {
+        return Ok(None);
+    }
+    limit
+        .parse()
+        .map(Some)
+        .map_err(|_| anyhow::anyhow!("invalid limit {limit} in client limits entry {entry}"))
+}
@@ -0,0 +1,180 @@
+use std::{
+    error::Error,
+    future::Future,
+    pin::Pin,
+    str::FromStr,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use hyper::{
+    body::HttpBody,
+    header::{CONTENT_TYPE, HeaderValue},
+    Body, Method, Request, Response, StatusCode,
+};
+use jsonrpsee::types::ErrorObjectOwned;
+use serde_json::json;
+use solana_rpc_client_api::config::RpcSendTransactionConfig;
+use solana_sdk::packet::PACKET_DATA_SIZE;
+use tower::{Layer, Service};
+
+use crate::{
+    errors::{
+        AtlasTxnSenderError, NO_LEADERS_AVAILABLE_CODE, RATE_LIMITED_CODE, SENDING_PAUSED_CODE,
+        UPSTREAM_RPC_ERROR_CODE,
+    },
+    rpc_server::{AtlasTxnSenderImpl, SendTransactionConfig},
+};
+
+const OCTET_STREAM: &str = "application/octet-stream";
+
+/// RawTransactionLayer serves `POST <path>` requests with a bincode serialized transaction as the body,
+/// everything else is passed through to the json rpc server
+#[derive(Clone)]
+pub struct RawTransactionLayer {
+    path: Arc<str>,
+    atlas_txn_sender: AtlasTxnSenderImpl,
+}
+
+impl RawTransactionLayer {
+    pub fn new(path: &str, atlas_txn_sender: AtlasTxnSenderImpl) -> Self {
+        Self {
+            path: Arc::from(path),
+            atlas_txn_sender,
+        }
+    }
+}
+
+impl<S> Layer<S> for RawTransactionLayer {
+    type Service = RawTransaction<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RawTransaction {
+            inner,
+            path: self.path.clone(),
+            atlas_txn_sender: self.atlas_txn_sender.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RawTransaction<S> {
+    inner: S,
+    path: Arc<str>,
+    atlas_txn_sender: AtlasTxnSenderImpl,
+}
+
+impl<S> Service<Request<Body>> for RawTransaction<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+    S::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = Box<dyn Error + Send + Sync + 'static>;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        if req.uri().path() != self.path.as_ref() || req.method() != Method::POST {
+            let fut = self.inner.call(req);
+            return Box::pin(async move { fut.await.map_err(Into::into) });
+        }
+        let atlas_txn_sender = self.atlas_txn_sender.clone();
+        Box::pin(async move {
+            let response = match send_raw_transaction(atlas_txn_sender, req).await {
+                Ok(signature) => json_response(StatusCode::OK, json!({ "signature": signature })),
+                Err(e) => json_response(http_status(&e), json!({ "error": e })),
+            };
+            Ok::<_, Self::Error>(response)
+        })
+    }
+}
+
+async fn send_raw_transaction(
+    atlas_txn_sender: AtlasTxnSenderImpl,
+    req: Request<Body>,
+) -> Result<String, ErrorObjectOwned> {
+    // parameters like `; charset=binary` don't change the media type
+    let is_octet_stream = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .and_then(|content_type| content_type.split(';').next())
+        .map_or(false, |media_type| media_type.trim().eq_ignore_ascii_case(OCTET_STREAM));
+    if !is_octet_stream {
+        return Err(invalid_params(format!("content type must be {OCTET_STREAM}")));
+    }
+    // raw transactions are for latency sensitive clients, so skip preflight like they would
+    let params = SendTransactionConfig {
+        config: RpcSendTransactionConfig {
+            skip_preflight: true,
+            max_retries: query_param(req.uri().query(), "maxRetries")?,
+            ..RpcSendTransactionConfig::default()
+        },
+        deadline: query_param(req.uri().query(), "deadline")?,
+        ..SendTransactionConfig::default()
+    };
+    let wire_transaction = read_body(req.into_body()).await?;
+    atlas_txn_sender
+        .send_wire_transaction(wire_transaction, params)
+        .await
+}
+
+/// query_param reads an optional query param
+fn query_param<T: FromStr>(query: Option<&str>, name: &str) -> Result<Option<T>, ErrorObjectOwned> {
+    let value = query
+        .unwrap_or_default()
+        .split('&')
+        .find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='));
+    match value {
+        Some(value) => value
+            .parse::<T>()
+            .map(Some)
+            .map_err(|_| invalid_params(format!("Invalid {name} provided"))),
+        None => Ok(None),
+    }
+}
+
+async fn read_body(mut body: Body) -> Result<Vec<u8>, ErrorObjectOwned> {
+    let mut bytes = vec![];
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| invalid_params(format!("failed to read body: {e}")))?;
+        if bytes.len() + chunk.len() > PACKET_DATA_SIZE {
+            return Err(AtlasTxnSenderError::Decode {
+                reason: "decoded too large".to_string(),
+            }
+            .into());
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+fn invalid_params(reason: String) -> ErrorObjectOwned {
+    AtlasTxnSenderError::InvalidParams { reason }.into()
+}
+
+/// http_status is 429 when rate limited, 503 when the request can be retried once leaders or the rpc
+/// are back or sending is resumed, and 400 otherwise
+fn http_status(e: &ErrorObjectOwned) -> StatusCode {
+    match e.code() {
+        RATE_LIMITED_CODE => StatusCode::TOO_MANY_REQUESTS,
+        NO_LEADERS_AVAILABLE_CODE | UPSTREAM_RPC_ERROR_CODE | SENDING_PAUSED_CODE => {
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+        _ => StatusCode::BAD_REQUEST,
+    }
+}
+
+fn json_response(status: StatusCode, body: serde_json::Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+        .body(Body::from(body.to_string()))
+        .expect("valid response")
+}
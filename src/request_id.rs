@@ -0,0 +1,97 @@
+use std::{
+    error::Error,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use hyper::{Body, Request, Response};
+use rand::{distributions::Alphanumeric, Rng};
+use tower::{Layer, Service};
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+// longer ids are cut off so a client can't blow up every log line
+const MAX_REQUEST_ID_LEN: usize = 64;
+const GENERATED_REQUEST_ID_LEN: usize = 20;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// current_request_id returns the id of the request being handled, None outside of with_request_id
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|request_id| request_id.clone()).ok()
+}
+
+/// with_request_id runs the future with request_id as the current request id
+pub async fn with_request_id<F: Future>(request_id: Option<String>, f: F) -> F::Output {
+    match request_id {
+        Some(request_id) => {
+            let request_id = request_id.chars().take(MAX_REQUEST_ID_LEN).collect();
+            REQUEST_ID.scope(request_id, f).await
+        }
+        None => f.await,
+    }
+}
+
+/// resolve_request_id returns the id the client set on the request, falling back to the current request id and
+/// then to a generated one
+pub fn resolve_request_id(request_id: Option<String>) -> String {
+    request_id
+        .or_else(current_request_id)
+        .unwrap_or_else(new_request_id)
+}
+
+fn new_request_id() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(GENERATED_REQUEST_ID_LEN)
+        .map(char::from)
+        .collect()
+}
+
+/// RequestIdLayer makes the `x-request-id` header available to rpc methods through current_request_id
+#[derive(Clone, Default)]
+pub struct RequestIdLayer;
+
+impl<S> Layer<S> for RequestIdLayer {
+    type Service = RequestId<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestId { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct RequestId<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for RequestId<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+    S::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = Box<dyn Error + Send + Sync + 'static>;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let request_id = req
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|request_id| request_id.to_str().ok())
+            .map(|request_id| request_id.to_string());
+        let fut = self.inner.call(req);
+        // requests without an id get one generated once they send a transaction
+        Box::pin(with_request_id(request_id, async move {
+            fut.await.map_err(Into::into)
+        }))
+    }
+}
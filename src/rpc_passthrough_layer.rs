@@ -0,0 +1,238 @@
+use std::{
+    collections::HashSet,
+    error::Error,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use cadence_macros::{statsd_count, statsd_time};
+use hyper::{
+    body::{Bytes, HttpBody},
+    header::{CONTENT_TYPE, HeaderValue},
+    Body, Method, Request, Response, StatusCode,
+};
+use jsonrpsee::types::ErrorObjectOwned;
+use serde::Deserialize;
+use serde_json::json;
+use tower::{Layer, Service};
+use tracing::debug;
+
+use crate::{auth::current_client_tag, errors::AtlasTxnSenderError};
+
+// read only methods wallets and sdks call alongside sendTransaction, forwarded unless RPC_PASSTHROUGH_METHODS is set
+pub const DEFAULT_PASSTHROUGH_METHODS: &[&str] = &[
+    "getAccountInfo",
+    "getBalance",
+    "getEpochInfo",
+    "getFeeForMessage",
+    "getGenesisHash",
+    "getMinimumBalanceForRentExemption",
+    "getMultipleAccounts",
+    "getRecentPrioritizationFees",
+    "getTokenAccountBalance",
+    "getTokenAccountsByOwner",
+    "getTransaction",
+    "getVersion",
+    "isBlockhashValid",
+    "simulateTransaction",
+];
+// bigger requests aren't buffered, they go to the json rpc server as is
+const MAX_PASSTHROUGH_BODY_SIZE: u64 = 1_000_000;
+const PASSTHROUGH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// RpcPassthroughLayer forwards json rpc requests for methods the sender doesn't implement to the rpc,
+/// as long as every method in the request is in the allowlist. Everything else is passed through to the
+/// json rpc server
+#[derive(Clone)]
+pub struct RpcPassthroughLayer {
+    upstream: Arc<Upstream>,
+}
+
+impl RpcPassthroughLayer {
+    pub fn new(rpc_url: &str, local_methods: HashSet<String>, allowed_methods: HashSet<String>) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(PASSTHROUGH_TIMEOUT)
+            .build()
+            .expect("passthrough client builds");
+        Self {
+            upstream: Arc::new(Upstream {
+                rpc_url: rpc_url.to_string(),
+                local_methods,
+                allowed_methods,
+                client,
+            }),
+        }
+    }
+
+    /// parses the comma separated methods that may be forwarded, DEFAULT_PASSTHROUGH_METHODS if unset
+    /// and none if empty
+    pub fn parse_methods(methods: Option<&str>) -> HashSet<String> {
+        match methods {
+            Some(methods) => methods
+                .split(',')
+                .map(|method| method.trim())
+                .filter(|method| !method.is_empty())
+                .map(|method| method.to_string())
+                .collect(),
+            None => DEFAULT_PASSTHROUGH_METHODS
+                .iter()
+                .map(|method| method.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl<S> Layer<S> for RpcPassthroughLayer {
+    type Service = RpcPassthrough<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RpcPassthrough {
+            inner,
+            upstream: self.upstream.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RpcPassthrough<S> {
+    inner: S,
+    upstream: Arc<Upstream>,
+}
+
+impl<S> Service<Request<Body>> for RpcPassthrough<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = Box<dyn Error + Send + Sync + 'static>;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let small_body = req
+            .body()
+            .size_hint()
+            .upper()
+            .map_or(false, |size| size <= MAX_PASSTHROUGH_BODY_SIZE);
+        if req.method() != Method::POST || !small_body || self.upstream.allowed_methods.is_empty() {
+            let fut = self.inner.call(req);
+            return Box::pin(async move { fut.await.map_err(Into::into) });
+        }
+        // the inner service was polled ready, so keep it for this request and leave a clone for the next
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let upstream = self.upstream.clone();
+        Box::pin(async move {
+            let (parts, body) = req.into_parts();
+            let body = hyper::body::to_bytes(body).await?;
+            let request = serde_json::from_slice::<RpcRequest>(&body).ok();
+            let request = match request {
+                Some(request) if upstream.forwardable(&request) => request,
+                _ => {
+                    let req = Request::from_parts(parts, Body::from(body));
+                    return inner.call(req).await.map_err(Into::into);
+                }
+            };
+            let response = match upstream.forward(body).await {
+                Ok(response) => response,
+                Err(e) => {
+                    debug!("failed to forward request to rpc: {}", e);
+                    upstream_error_response(&request, e)
+                }
+            };
+            Ok::<_, Self::Error>(response)
+        })
+    }
+}
+
+struct Upstream {
+    rpc_url: String,
+    local_methods: HashSet<String>,
+    allowed_methods: HashSet<String>,
+    client: reqwest::Client,
+}
+
+impl Upstream {
+    /// forwardable is true when no method in the request is served locally and all of them are allowed
+    fn forwardable(&self, request: &RpcRequest) -> bool {
+        let calls = request.calls();
+        !calls.is_empty()
+            && calls.iter().all(|call| {
+                !self.local_methods.contains(&call.method)
+                    && self.allowed_methods.contains(&call.method)
+            })
+    }
+
+    async fn forward(&self, body: Bytes) -> Result<Response<Body>, AtlasTxnSenderError> {
+        let client = current_client_tag();
+        statsd_count!("rpc_passthrough", 1, "client" => &client);
+        let start = Instant::now();
+        let upstream_error = |e: reqwest::Error| AtlasTxnSenderError::UpstreamRpc {
+            reason: e.to_string(),
+        };
+        let response = self
+            .client
+            .post(self.rpc_url.as_str())
+            .header(CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await
+            .map_err(upstream_error)?;
+        let status =
+            StatusCode::from_u16(response.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+        let body = response.bytes().await.map_err(upstream_error)?;
+        statsd_time!("rpc_passthrough_time", start.elapsed(), "client" => &client);
+        Ok(Response::builder()
+            .status(status)
+            .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+            .body(Body::from(body))
+            .expect("valid response"))
+    }
+}
+
+#[derive(Deserialize)]
+struct RpcCall {
+    method: String,
+    #[serde(default)]
+    id: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RpcRequest {
+    Single(RpcCall),
+    Batch(Vec<RpcCall>),
+}
+
+impl RpcRequest {
+    fn calls(&self) -> &[RpcCall] {
+        match self {
+            RpcRequest::Single(call) => std::slice::from_ref(call),
+            RpcRequest::Batch(calls) => calls,
+        }
+    }
+}
+
+/// upstream_error_response answers every call in the request with the error, so batches keep their ids
+fn upstream_error_response(request: &RpcRequest, e: AtlasTxnSenderError) -> Response<Body> {
+    let error = ErrorObjectOwned::from(e);
+    let error_response = |call: &RpcCall| json!({ "jsonrpc": "2.0", "id": call.id, "error": error });
+    let body = match request {
+        RpcRequest::Single(call) => error_response(call),
+        RpcRequest::Batch(calls) => json!(calls.iter().map(error_response).collect::<Vec<_>>()),
+    };
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+        .body(Body::from(body.to_string()))
+        .expect("valid response")
+}
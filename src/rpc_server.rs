@@ -2,109 +2,1122 @@ use std::{
     fmt::Debug,
     str::FromStr,
     sync::Arc,
-    time::{Instant, SystemTime},
+    time::{Duration, Instant, SystemTime},
 };
 
 use cadence_macros::{statsd_count, statsd_time};
 use jsonrpsee::{
-    core::{async_trait, RpcResult},
+    core::{async_trait, RpcResult, SubscriptionResult},
     proc_macros::rpc,
-    types::{error::INVALID_PARAMS_CODE, ErrorObjectOwned},
+    types::ErrorObjectOwned,
+    PendingSubscriptionSink, SubscriptionMessage,
 };
-use solana_rpc_client_api::config::RpcSendTransactionConfig;
-use solana_sdk::{commitment_config::CommitmentConfig, transaction::VersionedTransaction};
-use solana_transaction_status::UiTransactionEncoding;
+use futures::future::join_all;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::json;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_rpc_client_api::{
+    client_error::{Error as ClientError, ErrorKind as ClientErrorKind},
+    config::{
+        RpcContextConfig, RpcSendTransactionConfig, RpcSignatureStatusConfig,
+        RpcSignatureSubscribeConfig, RpcSimulateTransactionConfig,
+    },
+    custom_error::JSON_RPC_SERVER_ERROR_MIN_CONTEXT_SLOT_NOT_REACHED,
+    request::{RpcError, RpcRequest, MAX_GET_SIGNATURE_STATUSES_QUERY_ITEMS},
+    response::{Response as RpcResponse, RpcBlockhash, RpcResponseContext},
+};
+use solana_sdk::{
+    clock::{Slot, MAX_PROCESSING_AGE},
+    commitment_config::CommitmentConfig,
+    transaction::{TransactionError, VersionedTransaction},
+};
+use solana_transaction_status::{TransactionConfirmationStatus, UiTransactionEncoding};
+use tokio::{sync::broadcast::error::RecvError, time::sleep};
 use tracing::error;
 
 use crate::{
-    errors::invalid_request, transaction_store::TransactionData, txn_sender::TxnSender,
-    vendor::solana_rpc::decode_and_deserialize,
+    auth::{current_client_id, current_client_tag, ApiKeys},
+    errors::AtlasTxnSenderError,
+    grpc_geyser::parse_durable_nonce,
+    leader_tracker::{LeaderTracker, UpcomingLeader},
+    program_policy::ProgramPolicy,
+    rate_limiter::RateLimiter,
+    request_id::{resolve_request_id, with_request_id},
+    solana_rpc::{BlockhashStatus, RecentBlock, SolanaRpc, StreamHealth},
+    transaction_store::{SendAttempt, TransactionData, TransactionStatus, TransactionStore},
+    txn_sender::{get_nonce_account, TxnSender},
+    utils::now_unix_millis,
+    vendor::solana_rpc::{decode_and_deserialize, deserialize_wire},
 };
 
+/// SignatureStatus follows the shape of the solana rpc `getSignatureStatuses` response, `slot` is only set
+/// once the transaction has been seen in a block, `landingStatus` is the sender's view of the transaction
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SignatureStatus {
+    pub slot: Option<Slot>,
+    pub confirmations: Option<usize>,
+    pub err: Option<TransactionError>,
+    pub confirmation_status: Option<TransactionConfirmationStatus>,
+    pub landing_status: TransactionStatus,
+}
+
+/// TransactionLifecycle is everything the sender knows about a transaction it has sent
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionLifecycle {
+    pub signature: String,
+    pub status: Option<SignatureStatus>,
+    pub attempts: Vec<SendAttempt>,
+    // attempts between the first and last ones in attempts that weren't kept
+    pub dropped_attempts: usize,
+}
+
+/// SendTransactionConfig is the solana rpc sendTransaction config plus the fields we add to it
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SendTransactionConfig {
+    #[serde(flatten)]
+    pub config: RpcSendTransactionConfig,
+    // ties the transaction's logs to the request, takes precedence over the x-request-id header
+    pub request_id: Option<String>,
+    // unix timestamp in milliseconds after which the sender stops retrying the transaction
+    pub deadline: Option<u64>,
+}
+
+/// SendTransactionResult is the outcome of one transaction in a `sendTransactionBatch` request
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SendTransactionResult {
+    pub signature: Option<String>,
+    pub error: Option<ErrorObjectOwned>,
+}
+
+/// HealthReport is the readiness of the sender and the state of everything it depends on
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthReport {
+    pub ready: bool,
+    // why the sender isn't ready, empty when it is
+    pub issues: Vec<String>,
+    pub slot_stream: StreamHealth,
+    pub block_stream: StreamHealth,
+    pub geyser_slot: Option<Slot>,
+    pub rpc_slot: Option<Slot>,
+    // how many slots geyser is behind the rpc, negative when it's ahead
+    pub slot_gap: Option<i64>,
+    pub leader_schedule_age_ms: Option<u64>,
+    // leaders we're currently sending to that have a quic address
+    pub quic_leaders: usize,
+    pub sending_paused: bool,
+}
+
+// a stream that's quiet for longer than this is treated as dead, slots and blocks arrive every ~400ms
+const MAX_STREAM_SILENCE_MS: u64 = 10_000;
+const MAX_SLOT_GAP: i64 = 50;
+// the schedule is refreshed every minute, allow a couple of failed polls
+const MAX_LEADER_SCHEDULE_AGE_MS: u64 = 180_000;
+const HEALTH_RPC_TIMEOUT: Duration = Duration::from_secs(2);
+
+// how long a request waits for geyser to reach its min_context_slot before it's rejected, geyser can be
+// a slot or two behind the rpc the client got the slot from
+const MAX_MIN_CONTEXT_SLOT_WAIT: Duration = Duration::from_secs(1);
+
+// how long a transaction waits for its blockhash to show up on the geyser streams, the client's rpc can be a
+// slot or two ahead of them
+const MAX_BLOCKHASH_WAIT: Duration = Duration::from_secs(1);
+
+// deadlines further out than this are rejected
+const MAX_DEADLINE: Duration = Duration::from_secs(3600);
+// durable nonce transactions don't expire, without a deadline they're retried for this long
+const DEFAULT_DURABLE_NONCE_DEADLINE: Duration = Duration::from_secs(600);
+// nothing watches a cancelled transaction, so signature subscriptions check the block stream for it about once a slot
+const CANCELLED_LANDING_CHECK_INTERVAL: Duration = Duration::from_millis(400);
+
+// leaders are polled 1000 slots ahead, we can't return more than that
+const MAX_UPCOMING_LEADER_SLOTS: u64 = 1000;
+const DEFAULT_UPCOMING_LEADER_SLOTS: u64 = 100;
+
+// same limit as getSignatureStatuses so a batch can be checked in one call
+const MAX_SEND_TRANSACTION_BATCH_SIZE: usize = MAX_GET_SIGNATURE_STATUSES_QUERY_ITEMS;
+
 #[rpc(server)]
 pub trait AtlasTxnSender {
+    /// health returns the health report when the sender is ready, and a node unhealthy error carrying it otherwise
     #[method(name = "health")]
-    async fn health(&self) -> String;
+    async fn health(&self) -> RpcResult<HealthReport>;
     #[method(name = "sendTransaction")]
     async fn send_transaction(
         &self,
         txn: String,
-        params: RpcSendTransactionConfig,
+        params: SendTransactionConfig,
     ) -> RpcResult<String>;
+    #[method(name = "sendTransactionBatch")]
+    async fn send_transaction_batch(
+        &self,
+        txns: Vec<String>,
+        params: SendTransactionConfig,
+    ) -> RpcResult<Vec<SendTransactionResult>>;
+    #[method(name = "getSignatureStatuses")]
+    async fn get_signature_statuses(
+        &self,
+        signatures: Vec<String>,
+        config: Option<RpcSignatureStatusConfig>,
+    ) -> RpcResult<RpcResponse<Vec<Option<SignatureStatus>>>>;
+    #[method(name = "getTransactionLifecycle")]
+    async fn get_transaction_lifecycle(
+        &self,
+        signature: String,
+    ) -> RpcResult<Option<TransactionLifecycle>>;
+    #[method(name = "getUpcomingLeaders")]
+    async fn get_upcoming_leaders(&self, limit: Option<u64>) -> RpcResult<Vec<UpcomingLeader>>;
+    /// getLatestBlockhash is served from the geyser block stream unless the commitment is finalized
+    #[method(name = "getLatestBlockhash")]
+    async fn get_latest_blockhash(
+        &self,
+        config: Option<RpcContextConfig>,
+    ) -> RpcResult<RpcResponse<RpcBlockhash>>;
+    /// getSlot is served from the geyser slot stream at processed and the block stream at confirmed
+    #[method(name = "getSlot")]
+    async fn get_slot(&self, config: Option<RpcContextConfig>) -> RpcResult<Slot>;
+    /// getBlockHeight is served from the geyser block stream unless the commitment is finalized
+    #[method(name = "getBlockHeight")]
+    async fn get_block_height(&self, config: Option<RpcContextConfig>) -> RpcResult<u64>;
+    /// cancelTransaction stops rebroadcasting the transaction, it can still land if a leader already has it
+    #[method(name = "cancelTransaction")]
+    async fn cancel_transaction(&self, signature: String) -> RpcResult<bool>;
+    /// signatureSubscribe sends a single notification once the transaction lands or the sender gives up on it
+    #[subscription(name = "signatureSubscribe" => "signatureNotification", unsubscribe = "signatureUnsubscribe", item = RpcResponse<SignatureStatus>)]
+    async fn signature_subscribe(
+        &self,
+        signature: String,
+        config: Option<RpcSignatureSubscribeConfig>,
+    ) -> SubscriptionResult;
 }
 
+#[derive(Clone)]
 pub struct AtlasTxnSenderImpl {
     txn_sender: Arc<dyn TxnSender>,
+    transaction_store: Arc<dyn TransactionStore>,
+    solana_rpc: Arc<dyn SolanaRpc>,
+    rpc_client: Arc<RpcClient>,
+    leader_tracker: Arc<dyn LeaderTracker>,
+    rate_limiter: Arc<dyn RateLimiter>,
+    program_policy: Arc<dyn ProgramPolicy>,
+    api_keys: ApiKeys,
 }
 
 impl AtlasTxnSenderImpl {
-    pub fn new(txn_sender: Arc<dyn TxnSender>) -> Self {
-        Self { txn_sender }
+    pub fn new(
+        txn_sender: Arc<dyn TxnSender>,
+        transaction_store: Arc<dyn TransactionStore>,
+        solana_rpc: Arc<dyn SolanaRpc>,
+        rpc_client: Arc<RpcClient>,
+        leader_tracker: Arc<dyn LeaderTracker>,
+        rate_limiter: Arc<dyn RateLimiter>,
+        program_policy: Arc<dyn ProgramPolicy>,
+        api_keys: ApiKeys,
+    ) -> Self {
+        Self {
+            txn_sender,
+            transaction_store,
+            solana_rpc,
+            rpc_client,
+            leader_tracker,
+            rate_limiter,
+            program_policy,
+            api_keys,
+        }
     }
-}
 
-#[async_trait]
-impl AtlasTxnSenderServer for AtlasTxnSenderImpl {
-    async fn health(&self) -> String {
-        "ok".to_string()
+    /// send_wire_transaction sends an already serialized transaction, skipping base58/base64 decoding
+    pub async fn send_wire_transaction(
+        &self,
+        wire_transaction: Vec<u8>,
+        params: SendTransactionConfig,
+    ) -> RpcResult<String> {
+        statsd_count!("send_wire_transaction", 1, "client" => &current_client_tag());
+        self.check_client()?;
+        validate_send_transaction_params(&params)?;
+        let start = Instant::now();
+        let transaction = deserialize_transaction(wire_transaction, &params)?;
+        let signature = self.submit_transaction(transaction, &params).await?;
+        statsd_time!("send_wire_transaction_time", start.elapsed());
+        Ok(signature)
     }
-    async fn send_transaction(
+
+    /// send_encoded_transaction sends a base58 or base64 encoded transaction
+    pub async fn send_encoded_transaction(
         &self,
         txn: String,
-        params: RpcSendTransactionConfig,
+        params: SendTransactionConfig,
     ) -> RpcResult<String> {
-        statsd_count!("send_transaction", 1);
+        statsd_count!("send_transaction", 1, "client" => &current_client_tag());
+        self.check_client()?;
         validate_send_transaction_params(&params)?;
         let start = Instant::now();
-        let encoding = params.encoding.unwrap_or(UiTransactionEncoding::Base58);
-        let binary_encoding = encoding.into_binary_encoding().ok_or_else(|| {
-            invalid_request(&format!(
-                "unsupported encoding: {encoding}. Supported encodings: base58, base64"
-            ))
-        })?;
-        let (wire_transaction, versioned_transaction) =
-            match decode_and_deserialize::<VersionedTransaction>(txn, binary_encoding) {
-                Ok((wire_transaction, versioned_transaction)) => {
-                    (wire_transaction, versioned_transaction)
+        let transaction = decode_transaction(txn, &params)?;
+        let signature = self.submit_transaction(transaction, &params).await?;
+        statsd_time!("send_transaction_time", start.elapsed());
+        Ok(signature)
+    }
+
+    async fn send_encoded_transactions(
+        &self,
+        txns: Vec<String>,
+        params: SendTransactionConfig,
+    ) -> RpcResult<Vec<SendTransactionResult>> {
+        statsd_count!("send_transaction_batch", 1);
+        self.check_client()?;
+        validate_send_transaction_params(&params)?;
+        if txns.len() > MAX_SEND_TRANSACTION_BATCH_SIZE {
+            return Err(AtlasTxnSenderError::InvalidParams {
+                reason: format!(
+                    "Too many transactions provided; max {MAX_SEND_TRANSACTION_BATCH_SIZE}"
+                ),
+            }
+            .into());
+        }
+        self.check_not_paused()?;
+        self.wait_for_min_context_slot(params.config.min_context_slot)
+            .await?;
+        self.check_leaders()?;
+        let start = Instant::now();
+        let mut decoded_transactions: Vec<_> = txns
+            .into_iter()
+            .map(|txn| decode_transaction(txn, &params))
+            .collect();
+        // resubmitted transactions just get their signature back, they don't count towards the limits
+        let already_sent: Vec<bool> = decoded_transactions
+            .iter()
+            .map(|transaction| match transaction {
+                Ok(transaction) => self.already_sent(
+                    &transaction.versioned_transaction.signatures[0].to_string(),
+                ),
+                Err(_) => false,
+            })
+            .collect();
+        // transactions past what the client's limits allow are rejected, the rest are still sent. Limits are
+        // taken before the checks so they also cap the upstream calls the checks make
+        let client = current_client_tag();
+        let num_decoded = decoded_transactions
+            .iter()
+            .zip(&already_sent)
+            .filter(|(t, already_sent)| t.is_ok() && !**already_sent)
+            .count();
+        if num_decoded > 0 {
+            let mut remaining = self.rate_limiter.acquire(&client, num_decoded);
+            for (transaction, _) in decoded_transactions
+                .iter_mut()
+                .zip(&already_sent)
+                .filter(|(t, already_sent)| t.is_ok() && !**already_sent)
+            {
+                match &mut remaining {
+                    Ok(0) => {
+                        *transaction = Err(AtlasTxnSenderError::RateLimited {
+                            limit: "batch".to_string(),
+                            reason: "batch is larger than the client's remaining limits"
+                                .to_string(),
+                        })
+                    }
+                    Ok(allowed) => *allowed -= 1,
+                    Err(e) => *transaction = Err(e.clone()),
+                }
+            }
+        }
+        // checks and simulations are independent so run them concurrently
+        let client_ref = &client;
+        let params_ref = &params.config;
+        let checks = join_all(decoded_transactions.iter().zip(&already_sent).map(
+            |(transaction, already_sent)| async move {
+                match transaction {
+                    Ok(transaction) if !already_sent => {
+                        self.check_transaction(
+                            client_ref,
+                            &transaction.versioned_transaction,
+                            params_ref,
+                        )
+                        .await
+                    }
+                    _ => Ok(None),
+                }
+            },
+        ))
+        .await;
+        // transactions that failed a check aren't sent, so they give back what they took from the limits
+        let num_failed_checks = decoded_transactions
+            .iter()
+            .zip(&checks)
+            .filter(|(transaction, check)| transaction.is_ok() && check.is_err())
+            .count();
+        if num_failed_checks > 0 {
+            self.rate_limiter.refund(&client, num_failed_checks);
+        }
+        for (transaction, check) in decoded_transactions.iter_mut().zip(checks) {
+            match check {
+                Ok(last_valid_block_height) => {
+                    if let Ok(transaction) = transaction {
+                        transaction.last_valid_block_height = last_valid_block_height;
+                    }
+                }
+                Err(e) => *transaction = Err(e),
+            }
+        }
+        let mut results = Vec::with_capacity(decoded_transactions.len());
+        let mut transactions = Vec::with_capacity(decoded_transactions.len());
+        for (transaction, already_sent) in decoded_transactions.into_iter().zip(already_sent) {
+            match transaction {
+                Ok(transaction) => {
+                    let signature = transaction.versioned_transaction.signatures[0].to_string();
+                    results.push(SendTransactionResult {
+                        signature: Some(signature),
+                        error: None,
+                    });
+                    if !already_sent {
+                        transactions.push(transaction);
+                    }
                 }
                 Err(e) => {
-                    return Err(invalid_request(&e.to_string()));
+                    results.push(SendTransactionResult {
+                        signature: None,
+                        error: Some(e.into()),
+                    });
                 }
-            };
-        let signature = versioned_transaction.signatures[0].to_string();
-        let transaction = TransactionData {
-            wire_transaction,
-            versioned_transaction,
-            sent_at: Instant::now(),
-            sent_at_unix: SystemTime::now(),
-            retry_count: 0,
-            max_retries: params.max_retries,
-        };
-        self.txn_sender.send_transaction(transaction, Some(CommitmentConfig {
-            commitment: params.preflight_commitment.unwrap_or_default()
-        }));
-        statsd_time!("send_transaction_time", start.elapsed());
+            }
+        }
+        statsd_count!("send_transaction", transactions.len() as i64, "client" => &current_client_tag());
+        self.txn_sender.send_transactions(transactions);
+        statsd_time!("send_transaction_batch_time", start.elapsed());
+        Ok(results)
+    }
+
+    async fn submit_transaction(
+        &self,
+        mut transaction: TransactionData,
+        params: &SendTransactionConfig,
+    ) -> RpcResult<String> {
+        let signature = transaction.versioned_transaction.signatures[0].to_string();
+        if self.already_sent(&signature) {
+            return Ok(signature);
+        }
+        self.check_not_paused()?;
+        self.wait_for_min_context_slot(params.config.min_context_slot)
+            .await?;
+        self.check_leaders()?;
+        // limits are taken before the checks so they also cap the upstream calls the checks make
+        let client = current_client_tag();
+        self.rate_limiter.acquire(&client, 1)?;
+        match self
+            .check_transaction(&client, &transaction.versioned_transaction, &params.config)
+            .await
+        {
+            Ok(last_valid_block_height) => {
+                transaction.last_valid_block_height = last_valid_block_height;
+            }
+            Err(e) => {
+                // it isn't sent, so it gives back what it took from the limits
+                self.rate_limiter.refund(&client, 1);
+                return Err(e.into());
+            }
+        }
+        self.txn_sender.send_transaction(transaction);
         Ok(signature)
     }
+
+    /// check_transaction runs the client's program policy, the blockhash or durable nonce check and the preflight
+    /// simulation, and returns the blockhash's last valid block height
+    async fn check_transaction(
+        &self,
+        client: &str,
+        transaction: &VersionedTransaction,
+        params: &RpcSendTransactionConfig,
+    ) -> Result<Option<u64>, AtlasTxnSenderError> {
+        self.program_policy.check(client, transaction).await?;
+        let last_valid_block_height = self.check_blockhash(transaction).await?;
+        self.check_durable_nonce(transaction).await?;
+        self.run_preflight(transaction, params).await?;
+        Ok(last_valid_block_height)
+    }
+
+    /// check_durable_nonce rejects durable nonce transactions whose nonce account already holds a different nonce,
+    /// the confirmation watcher only sees the account change after it's been sent. Nonce accounts loaded from a
+    /// lookup table and accounts that aren't initialized nonce accounts are let through
+    async fn check_durable_nonce(
+        &self,
+        transaction: &VersionedTransaction,
+    ) -> Result<(), AtlasTxnSenderError> {
+        let Some(nonce_account) = get_nonce_account(transaction) else {
+            return Ok(());
+        };
+        // processed, so a nonce the client read at any commitment isn't ahead of ours
+        let account = self
+            .rpc_client
+            .get_account_with_commitment(nonce_account, CommitmentConfig::processed())
+            .await
+            .map_err(|e| rpc_client_error(e, "failed to fetch nonce account"))?
+            .value;
+        let Some(nonce) = account.and_then(|account| parse_durable_nonce(&account.data)) else {
+            return Ok(());
+        };
+        if nonce != transaction.message.recent_blockhash().to_string() {
+            statsd_count!("nonce_rejected", 1);
+            return Err(AtlasTxnSenderError::NonceAdvanced {
+                nonce_account: nonce_account.to_string(),
+                nonce,
+            });
+        }
+        Ok(())
+    }
+
+    /// already_sent is true when the sender is still working on the signature or it already landed, resubmitting
+    /// it returns the signature without a preflight, a rate limit check or another send to the leaders
+    fn already_sent(&self, signature: &str) -> bool {
+        let already_sent = self.txn_sender.is_tracking(signature)
+            || self.transaction_store.get_status(signature) == Some(TransactionStatus::Landed);
+        if already_sent {
+            statsd_count!("transactions_resubmitted", 1, "client" => &current_client_tag());
+        }
+        already_sent
+    }
+
+    /// wait_for_min_context_slot holds the request until the geyser slot reaches min_context_slot, and
+    /// rejects it like solana rpc does if that takes longer than MAX_MIN_CONTEXT_SLOT_WAIT
+    async fn wait_for_min_context_slot(
+        &self,
+        min_context_slot: Option<Slot>,
+    ) -> Result<(), AtlasTxnSenderError> {
+        let Some(min_context_slot) = min_context_slot else {
+            return Ok(());
+        };
+        let start = Instant::now();
+        let mut waited = false;
+        loop {
+            let context_slot = self.solana_rpc.get_next_slot().unwrap_or_default();
+            if context_slot >= min_context_slot {
+                if waited {
+                    statsd_time!("min_context_slot_wait_time", start.elapsed());
+                }
+                return Ok(());
+            }
+            if start.elapsed() >= MAX_MIN_CONTEXT_SLOT_WAIT {
+                statsd_count!("min_context_slot_not_reached", 1);
+                return Err(AtlasTxnSenderError::MinContextSlotNotReached {
+                    context_slot: Some(context_slot),
+                });
+            }
+            waited = true;
+            sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    /// check_client fails when api keys are required but the request isn't attributed to a client, which is the
+    /// case for websocket calls since jsonrpsee handles them outside of the http request they connected with
+    fn check_client(&self) -> Result<(), AtlasTxnSenderError> {
+        if self.api_keys.is_enabled() && current_client_id().is_none() {
+            statsd_count!("unattributed_request", 1);
+            return Err(AtlasTxnSenderError::Unauthorized {
+                reason: "websocket requests can't send or cancel transactions, use http".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// check_not_paused fails while sending is paused through the admin api
+    fn check_not_paused(&self) -> Result<(), AtlasTxnSenderError> {
+        if self.txn_sender.is_paused() {
+            statsd_count!("sending_paused", 1);
+            return Err(AtlasTxnSenderError::SendingPaused);
+        }
+        Ok(())
+    }
+
+    /// check_leaders fails when none of the leaders we'd send to can be reached over quic
+    fn check_leaders(&self) -> Result<(), AtlasTxnSenderError> {
+        let leaders = self.leader_tracker.get_leaders();
+        if !leaders.iter().any(|leader| leader.tpu_quic.is_some()) {
+            statsd_count!("no_leaders_available", 1);
+            return Err(AtlasTxnSenderError::NoLeadersAvailable);
+        }
+        Ok(())
+    }
+
+    /// check_blockhash rejects transactions whose recent blockhash expired or isn't from a recent block, instead
+    /// of retrying them until they time out, and returns the blockhash's last valid block height. Blockhashes are
+    /// let through when we may have missed their block
+    async fn check_blockhash(
+        &self,
+        transaction: &VersionedTransaction,
+    ) -> Result<Option<u64>, AtlasTxnSenderError> {
+        // the recent blockhash of a durable nonce transaction is the nonce, it never shows up on the block stream
+        if transaction.uses_durable_nonce() {
+            return Ok(None);
+        }
+        let blockhash = transaction.message.recent_blockhash().to_string();
+        let start = Instant::now();
+        loop {
+            match self.solana_rpc.get_blockhash_status(&blockhash) {
+                BlockhashStatus::Valid {
+                    last_valid_block_height,
+                } => return Ok(Some(last_valid_block_height)),
+                BlockhashStatus::Unknown => {
+                    statsd_count!("blockhash_unchecked", 1);
+                    return Ok(None);
+                }
+                BlockhashStatus::Expired {
+                    last_valid_block_height,
+                    block_height,
+                } => {
+                    statsd_count!("blockhash_rejected", 1, "reason" => "expired");
+                    return Err(AtlasTxnSenderError::BlockhashExpired {
+                        blockhash,
+                        last_valid_block_height,
+                        block_height,
+                    });
+                }
+                BlockhashStatus::NotFound if start.elapsed() >= MAX_BLOCKHASH_WAIT => {
+                    statsd_count!("blockhash_rejected", 1, "reason" => "not_found");
+                    return Err(AtlasTxnSenderError::BlockhashNotFound { blockhash });
+                }
+                BlockhashStatus::NotFound => sleep(Duration::from_millis(50)).await,
+            }
+        }
+    }
+
+    /// geyser_block returns the latest geyser block for reads at processed or confirmed commitment, None when
+    /// the read has to go to the rpc because it's at finalized (the default, like solana rpc) or no block was seen yet
+    fn geyser_block(&self, config: &Option<RpcContextConfig>) -> Option<RecentBlock> {
+        if commitment(config).is_finalized() {
+            return None;
+        }
+        self.solana_rpc.get_latest_block()
+    }
+
+    /// forward_to_rpc serves a read the geyser streams can't answer from the upstream rpc
+    async fn forward_to_rpc<T: DeserializeOwned>(
+        &self,
+        request: RpcRequest,
+        config: Option<RpcContextConfig>,
+    ) -> Result<T, AtlasTxnSenderError> {
+        statsd_count!("geyser_state_rpc_fallback", 1, "method" => &request.to_string());
+        self.rpc_client
+            .send(request, json!([config]))
+            .await
+            .map_err(|e| rpc_client_error(e, &format!("failed to call {request}")))
+    }
+
+    /// run_preflight simulates the transaction against the upstream rpc, the same check solana rpc runs
+    /// in sendTransaction when skip_preflight is false
+    async fn run_preflight(
+        &self,
+        transaction: &VersionedTransaction,
+        params: &RpcSendTransactionConfig,
+    ) -> Result<(), AtlasTxnSenderError> {
+        if params.skip_preflight {
+            return Ok(());
+        }
+        let start = Instant::now();
+        let simulate_config = RpcSimulateTransactionConfig {
+            sig_verify: true,
+            commitment: Some(CommitmentConfig {
+                commitment: params.preflight_commitment.unwrap_or_default(),
+            }),
+            encoding: Some(UiTransactionEncoding::Base64),
+            min_context_slot: params.min_context_slot,
+            ..RpcSimulateTransactionConfig::default()
+        };
+        let simulation = self
+            .rpc_client
+            .simulate_transaction_with_config(transaction, simulate_config)
+            .await
+            .map_err(|e| {
+                let e = rpc_client_error(e, "failed to simulate transaction");
+                if let AtlasTxnSenderError::UpstreamRpc { .. } = e {
+                    statsd_count!("preflight_rpc_error", 1);
+                }
+                e
+            })?;
+        statsd_time!("preflight_time", start.elapsed());
+        if let Some(err) = simulation.value.err.clone() {
+            statsd_count!("preflight_failure", 1);
+            return Err(AtlasTxnSenderError::PreflightFailure {
+                err,
+                result: simulation.value,
+            });
+        }
+        Ok(())
+    }
+
+    /// get_health checks the geyser streams, the rpc, and the leader schedule the sender depends on
+    pub async fn get_health(&self) -> HealthReport {
+        let mut issues = vec![];
+        let slot_stream = self.solana_rpc.get_slot_stream_health();
+        let block_stream = self.solana_rpc.get_block_stream_health();
+        for (name, stream) in [("slot", &slot_stream), ("block", &block_stream)] {
+            if !stream.connected {
+                issues.push(format!("geyser {name} stream is disconnected"));
+            } else if stream
+                .last_message_age_ms
+                .map_or(true, |age| age > MAX_STREAM_SILENCE_MS)
+            {
+                issues.push(format!(
+                    "geyser {name} stream has had no messages for over {MAX_STREAM_SILENCE_MS}ms"
+                ));
+            }
+        }
+        let geyser_slot = self.solana_rpc.get_next_slot();
+        let rpc_slot = tokio::time::timeout(
+            HEALTH_RPC_TIMEOUT,
+            self.rpc_client
+                .get_slot_with_commitment(CommitmentConfig::processed()),
+        )
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|slot| slot.map_err(|e| e.to_string()));
+        let rpc_slot = match rpc_slot {
+            Ok(rpc_slot) => Some(rpc_slot),
+            Err(e) => {
+                issues.push(format!("failed to get rpc slot: {e}"));
+                None
+            }
+        };
+        let slot_gap = geyser_slot
+            .zip(rpc_slot)
+            .map(|(geyser_slot, rpc_slot)| rpc_slot as i64 - geyser_slot as i64);
+        if let Some(slot_gap) = slot_gap.filter(|gap| *gap > MAX_SLOT_GAP) {
+            issues.push(format!(
+                "geyser is {slot_gap} slots behind the rpc; max {MAX_SLOT_GAP}"
+            ));
+        }
+        let leader_schedule_age_ms = self.leader_tracker.get_leader_schedule_age_ms();
+        match leader_schedule_age_ms {
+            None => issues.push("leader schedule hasn't been fetched".to_string()),
+            Some(age) if age > MAX_LEADER_SCHEDULE_AGE_MS => issues.push(format!(
+                "leader schedule is {age}ms old; max {MAX_LEADER_SCHEDULE_AGE_MS}ms"
+            )),
+            Some(_) => {}
+        }
+        let quic_leaders = self
+            .leader_tracker
+            .get_leaders()
+            .iter()
+            .filter(|leader| leader.tpu_quic.is_some())
+            .count();
+        if quic_leaders == 0 {
+            issues.push("no upcoming leaders have a quic address".to_string());
+        }
+        let sending_paused = self.txn_sender.is_paused();
+        if sending_paused {
+            issues.push("sending is paused".to_string());
+        }
+        if !issues.is_empty() {
+            statsd_count!("health_not_ready", 1);
+        }
+        HealthReport {
+            ready: issues.is_empty(),
+            issues,
+            slot_stream,
+            block_stream,
+            geyser_slot,
+            rpc_slot,
+            slot_gap,
+            leader_schedule_age_ms,
+            quic_leaders,
+            sending_paused,
+        }
+    }
+
+    pub fn get_signature_status(&self, signature: &str) -> Option<SignatureStatus> {
+        // blocks are streamed with confirmed commitment, so anything we've seen there is at least confirmed
+        if let Some(landed_transaction) = self.solana_rpc.get_landed_transaction(signature) {
+            return Some(SignatureStatus {
+                slot: Some(landed_transaction.slot),
+                confirmations: Some(0),
+                err: landed_transaction.err,
+                confirmation_status: Some(TransactionConfirmationStatus::Confirmed),
+                landing_status: TransactionStatus::Landed,
+            });
+        }
+        // transactions sent with maxRetries 0 aren't queued for retries, only watched until they land
+        let landing_status = match self.transaction_store.get_status(signature) {
+            Some(landing_status) => landing_status,
+            None if self.txn_sender.is_tracking(signature) => TransactionStatus::Pending,
+            None => return None,
+        };
+        Some(SignatureStatus {
+            slot: None,
+            confirmations: None,
+            err: None,
+            confirmation_status: None,
+            landing_status,
+        })
+    }
+
+    fn get_finished_signature_status(&self, signature: &str) -> Option<SignatureStatus> {
+        self.get_signature_status(signature)
+            .filter(|status| status.landing_status.is_finished())
+    }
+
+    fn rpc_response<T>(&self, value: T) -> RpcResponse<T> {
+        RpcResponse {
+            context: RpcResponseContext {
+                slot: self.solana_rpc.get_next_slot().unwrap_or_default(),
+                api_version: None,
+            },
+            value,
+        }
+    }
+}
+
+#[async_trait]
+impl AtlasTxnSenderServer for AtlasTxnSenderImpl {
+    async fn health(&self) -> RpcResult<HealthReport> {
+        let health = self.get_health().await;
+        if !health.ready {
+            return Err(AtlasTxnSenderError::NodeUnhealthy { report: health }.into());
+        }
+        Ok(health)
+    }
+    async fn send_transaction(
+        &self,
+        txn: String,
+        params: SendTransactionConfig,
+    ) -> RpcResult<String> {
+        let request_id = resolve_request_id(params.request_id.clone());
+        with_request_id(Some(request_id), self.send_encoded_transaction(txn, params)).await
+    }
+    async fn send_transaction_batch(
+        &self,
+        txns: Vec<String>,
+        params: SendTransactionConfig,
+    ) -> RpcResult<Vec<SendTransactionResult>> {
+        // every transaction in the batch shares the request id
+        let request_id = resolve_request_id(params.request_id.clone());
+        with_request_id(Some(request_id), self.send_encoded_transactions(txns, params)).await
+    }
+    async fn get_signature_statuses(
+        &self,
+        signatures: Vec<String>,
+        _config: Option<RpcSignatureStatusConfig>,
+    ) -> RpcResult<RpcResponse<Vec<Option<SignatureStatus>>>> {
+        statsd_count!("get_signature_statuses", 1);
+        if signatures.len() > MAX_GET_SIGNATURE_STATUSES_QUERY_ITEMS {
+            return Err(AtlasTxnSenderError::InvalidParams {
+                reason: format!(
+                    "Too many inputs provided; max {MAX_GET_SIGNATURE_STATUSES_QUERY_ITEMS}"
+                ),
+            }
+            .into());
+        }
+        // we don't keep transaction history, search_transaction_history has nothing to search
+        let statuses = signatures
+            .iter()
+            .map(|signature| self.get_signature_status(signature))
+            .collect();
+        Ok(self.rpc_response(statuses))
+    }
+    async fn get_transaction_lifecycle(
+        &self,
+        signature: String,
+    ) -> RpcResult<Option<TransactionLifecycle>> {
+        statsd_count!("get_transaction_lifecycle", 1);
+        let status = self.get_signature_status(&signature);
+        let history = self.transaction_store.get_attempts(&signature);
+        if status.is_none() && history.is_none() {
+            return Ok(None);
+        }
+        // attempts name the leaders a transaction was sent to, so only the client that sent it can see them
+        let (attempts, dropped_attempts) = history
+            .filter(|history| history.client_id == current_client_id())
+            .map_or((vec![], 0), |history| (history.attempts, history.dropped));
+        Ok(Some(TransactionLifecycle {
+            signature,
+            status,
+            attempts,
+            dropped_attempts,
+        }))
+    }
+    async fn get_upcoming_leaders(&self, limit: Option<u64>) -> RpcResult<Vec<UpcomingLeader>> {
+        statsd_count!("get_upcoming_leaders", 1);
+        let limit = limit.unwrap_or(DEFAULT_UPCOMING_LEADER_SLOTS);
+        if limit > MAX_UPCOMING_LEADER_SLOTS {
+            return Err(AtlasTxnSenderError::InvalidParams {
+                reason: format!("limit too large; max {MAX_UPCOMING_LEADER_SLOTS}"),
+            }
+            .into());
+        }
+        Ok(self.leader_tracker.get_upcoming_leaders(limit))
+    }
+    async fn get_latest_blockhash(
+        &self,
+        config: Option<RpcContextConfig>,
+    ) -> RpcResult<RpcResponse<RpcBlockhash>> {
+        statsd_count!("get_latest_blockhash", 1);
+        let Some(block) = self.geyser_block(&config) else {
+            return Ok(self
+                .forward_to_rpc(RpcRequest::GetLatestBlockhash, config)
+                .await?);
+        };
+        check_min_context_slot(&config, block.slot)?;
+        Ok(RpcResponse {
+            context: RpcResponseContext {
+                slot: block.slot,
+                api_version: None,
+            },
+            value: RpcBlockhash {
+                blockhash: block.blockhash,
+                last_valid_block_height: block.block_height + MAX_PROCESSING_AGE as u64,
+            },
+        })
+    }
+
+    async fn get_slot(&self, config: Option<RpcContextConfig>) -> RpcResult<Slot> {
+        statsd_count!("get_slot", 1);
+        let slot = if commitment(&config).is_processed() {
+            self.solana_rpc.get_next_slot()
+        } else {
+            self.geyser_block(&config).map(|block| block.slot)
+        };
+        let Some(slot) = slot else {
+            return Ok(self.forward_to_rpc(RpcRequest::GetSlot, config).await?);
+        };
+        check_min_context_slot(&config, slot)?;
+        Ok(slot)
+    }
+
+    async fn get_block_height(&self, config: Option<RpcContextConfig>) -> RpcResult<u64> {
+        statsd_count!("get_block_height", 1);
+        let Some(block) = self.geyser_block(&config) else {
+            return Ok(self
+                .forward_to_rpc(RpcRequest::GetBlockHeight, config)
+                .await?);
+        };
+        check_min_context_slot(&config, block.slot)?;
+        Ok(block.block_height)
+    }
+
+    async fn cancel_transaction(&self, signature: String) -> RpcResult<bool> {
+        statsd_count!("cancel_transaction", 1);
+        self.check_client()?;
+        // clients can only cancel transactions they sent
+        let owner = self.txn_sender.get_client_id(&signature);
+        if owner.map_or(false, |client_id| client_id != current_client_id()) {
+            statsd_count!("cancel_transaction_not_owner", 1, "client" => &current_client_tag());
+            return Ok(false);
+        }
+        Ok(self.txn_sender.cancel_transaction(&signature))
+    }
+    async fn signature_subscribe(
+        &self,
+        pending: PendingSubscriptionSink,
+        signature: String,
+        _config: Option<RpcSignatureSubscribeConfig>,
+    ) -> SubscriptionResult {
+        statsd_count!("signature_subscribe", 1);
+        // subscribe before checking the current status so an outcome recorded in between isn't missed
+        let mut outcomes = self.transaction_store.subscribe_outcomes();
+        let sink = pending.accept().await?;
+        let mut status = self.get_finished_signature_status(&signature);
+        // a cancelled transaction can still land if a leader already has it, so it's only reported once it's seen
+        // landing or it's sent again and finishes
+        while status
+            .as_ref()
+            .map_or(true, |status| status.landing_status == TransactionStatus::Cancelled)
+        {
+            let cancelled = status.is_some();
+            tokio::select! {
+                _ = sink.closed() => return Ok(()),
+                outcome = outcomes.recv() => match outcome {
+                    Ok((outcome_signature, _)) if outcome_signature != signature => continue,
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => return Ok(()),
+                },
+                _ = sleep(CANCELLED_LANDING_CHECK_INTERVAL), if cancelled => {}
+            }
+            status = self.get_finished_signature_status(&signature);
+        }
+        let mut status = status.unwrap();
+        // clients like web3.js take a notification without an error as the transaction succeeding. Expired and
+        // dropped are recorded once the confirmation watcher stops, so the transaction can't land after them
+        if status.landing_status != TransactionStatus::Landed {
+            status.err = Some(TransactionError::BlockhashNotFound);
+        }
+        let message = SubscriptionMessage::from_json(&self.rpc_response(status))?;
+        sink.send(message).await?;
+        Ok(())
+    }
+}
+
+/// commitment is the commitment a read is at, finalized if unset like solana rpc
+fn commitment(config: &Option<RpcContextConfig>) -> CommitmentConfig {
+    config
+        .as_ref()
+        .and_then(|config| config.commitment)
+        .unwrap_or_default()
 }
 
+/// check_min_context_slot rejects a read served at context_slot when it's below the request's min_context_slot,
+/// like solana rpc does
+fn check_min_context_slot(
+    config: &Option<RpcContextConfig>,
+    context_slot: Slot,
+) -> Result<(), AtlasTxnSenderError> {
+    match config.as_ref().and_then(|config| config.min_context_slot) {
+        Some(min_context_slot) if context_slot < min_context_slot => {
+            statsd_count!("min_context_slot_not_reached", 1);
+            Err(AtlasTxnSenderError::MinContextSlotNotReached {
+                context_slot: Some(context_slot),
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// rpc_client_error maps a failed call to the upstream rpc, keeping min context slot errors so clients know
+/// they can retry
+fn rpc_client_error(e: ClientError, action: &str) -> AtlasTxnSenderError {
+    match e.kind() {
+        // the rpc checks min_context_slot against its bank at the request's commitment
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. })
+            if *code == JSON_RPC_SERVER_ERROR_MIN_CONTEXT_SLOT_NOT_REACHED =>
+        {
+            statsd_count!("min_context_slot_not_reached", 1);
+            AtlasTxnSenderError::MinContextSlotNotReached { context_slot: None }
+        }
+        _ => AtlasTxnSenderError::UpstreamRpc {
+            reason: format!("{action}: {e}"),
+        },
+    }
+}
+
+/// validate_send_transaction_params checks the config before any transaction is decoded, each field is handled like
+/// solana rpc's sendTransaction:
+/// - skip_preflight: skips the simulateTransaction preflight against RPC_URL
+/// - preflight_commitment: the commitment the preflight simulates at, finalized if unset. Landing is always
+///   detected from confirmed blocks
+/// - encoding: base58 if unset, base64, anything else is an unsupported config error
+/// - max_retries: how many times the retry loop resends the transaction, 0 sends it once, unset retries until it
+///   lands or the confirmation watcher gives up
+/// - min_context_slot: waits up to MAX_MIN_CONTEXT_SLOT_WAIT for the geyser slot to reach it, and is passed to the
+///   preflight so the rpc checks it at the preflight commitment
+/// - deadline: not in solana rpc, must be in the future and within MAX_DEADLINE. Durable nonce transactions without
+///   one get DEFAULT_DURABLE_NONCE_DEADLINE
 fn validate_send_transaction_params(
-    params: &RpcSendTransactionConfig,
-) -> Result<(), ErrorObjectOwned> {
-    if !params.skip_preflight {
-        return Err(invalid_request("running preflight check is not supported"));
+    params: &SendTransactionConfig,
+) -> Result<(), AtlasTxnSenderError> {
+    let encoding = params.config.encoding.unwrap_or(UiTransactionEncoding::Base58);
+    if encoding.into_binary_encoding().is_none() {
+        return Err(unsupported_encoding(encoding));
+    }
+    if let Some(deadline) = params.deadline {
+        let now = now_unix_millis();
+        if deadline <= now {
+            return Err(AtlasTxnSenderError::InvalidParams {
+                reason: "deadline has already passed".to_string(),
+            });
+        }
+        if deadline - now > MAX_DEADLINE.as_millis() as u64 {
+            return Err(AtlasTxnSenderError::InvalidParams {
+                reason: format!("deadline too far out; max {}s from now", MAX_DEADLINE.as_secs()),
+            });
+        }
     }
     Ok(())
 }
 
-fn param<T: FromStr>(param_str: &str, thing: &str) -> Result<T, ErrorObjectOwned> {
-    param_str.parse::<T>().map_err(|_e| {
-        ErrorObjectOwned::owned(
-            INVALID_PARAMS_CODE,
-            format!("Invalid Request: Invalid {thing} provided"),
-            None::<String>,
-        )
-    })
+fn decode_transaction(
+    txn: String,
+    params: &SendTransactionConfig,
+) -> Result<TransactionData, AtlasTxnSenderError> {
+    let encoding = params.config.encoding.unwrap_or(UiTransactionEncoding::Base58);
+    let binary_encoding = encoding
+        .into_binary_encoding()
+        .ok_or_else(|| unsupported_encoding(encoding))?;
+    let (wire_transaction, versioned_transaction) =
+        decode_and_deserialize::<VersionedTransaction>(txn, binary_encoding)?;
+    verify_signatures(&versioned_transaction)?;
+    Ok(new_transaction_data(
+        wire_transaction,
+        versioned_transaction,
+        params,
+    ))
+}
+
+fn unsupported_encoding(encoding: UiTransactionEncoding) -> AtlasTxnSenderError {
+    AtlasTxnSenderError::UnsupportedConfig {
+        field: "encoding".to_string(),
+        reason: format!("{encoding}. Supported encodings: base58, base64"),
+    }
+}
+
+fn deserialize_transaction(
+    wire_transaction: Vec<u8>,
+    params: &SendTransactionConfig,
+) -> Result<TransactionData, AtlasTxnSenderError> {
+    let (wire_transaction, versioned_transaction) =
+        deserialize_wire::<VersionedTransaction>(wire_transaction)?;
+    verify_signatures(&versioned_transaction)?;
+    Ok(new_transaction_data(
+        wire_transaction,
+        versioned_transaction,
+        params,
+    ))
+}
+
+/// verify_signatures rejects transactions with a missing or invalid signature, they can't land and would be sent
+/// to every leader on our stake until they're given up on
+fn verify_signatures(transaction: &VersionedTransaction) -> Result<(), AtlasTxnSenderError> {
+    let start = Instant::now();
+    // verify_with_results only checks the signatures that are there, sanitize checks every signer has one
+    transaction
+        .sanitize()
+        .map_err(|e| AtlasTxnSenderError::Decode {
+            reason: format!("invalid transaction: {e}"),
+        })?;
+    let invalid_signatures: Vec<usize> = transaction
+        .verify_with_results()
+        .iter()
+        .enumerate()
+        .filter(|(_, valid)| !**valid)
+        .map(|(index, _)| index)
+        .collect();
+    statsd_time!("signature_verification_time", start.elapsed());
+    if !invalid_signatures.is_empty() {
+        statsd_count!("signature_verification_failure", 1, "client" => &current_client_tag());
+        return Err(AtlasTxnSenderError::SignatureVerificationFailure { invalid_signatures });
+    }
+    Ok(())
+}
+
+fn new_transaction_data(
+    wire_transaction: Vec<u8>,
+    versioned_transaction: VersionedTransaction,
+    params: &SendTransactionConfig,
+) -> TransactionData {
+    let durable_nonce = versioned_transaction.uses_durable_nonce();
+    let deadline = params.deadline.or_else(|| {
+        durable_nonce.then(|| now_unix_millis() + DEFAULT_DURABLE_NONCE_DEADLINE.as_millis() as u64)
+    });
+    TransactionData {
+        wire_transaction,
+        versioned_transaction,
+        sent_at: Instant::now(),
+        sent_at_unix: SystemTime::now(),
+        retry_count: 0,
+        max_retries: params.config.max_retries,
+        client_id: current_client_id(),
+        request_id: resolve_request_id(None),
+        last_valid_block_height: None,
+        durable_nonce,
+        deadline,
+    }
+}
+
+fn param<T: FromStr>(param_str: &str, thing: &str) -> Result<T, AtlasTxnSenderError> {
+    param_str
+        .parse::<T>()
+        .map_err(|_e| AtlasTxnSenderError::InvalidParams {
+            reason: format!("Invalid {thing} provided"),
+        })
 }
 
 fn log_error<T: Debug>(metric: &str) -> impl Fn(T) -> T {
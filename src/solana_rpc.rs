@@ -1,11 +1,61 @@
-use solana_sdk::{clock::UnixTimestamp, commitment_config::CommitmentConfig};
-use tonic::async_trait;
+use serde::{Deserialize, Serialize};
+use solana_sdk::{clock::Slot, transaction::TransactionError};
+
+/// LandedTransaction is what we know about a transaction we've seen in a block
+#[derive(Clone, Debug)]
+pub struct LandedTransaction {
+    pub slot: Slot,
+    pub err: Option<TransactionError>,
+}
+
+/// RecentBlock is a confirmed block seen on the geyser block stream
+#[derive(Clone, Debug)]
+pub struct RecentBlock {
+    pub slot: Slot,
+    pub blockhash: String,
+    pub block_height: u64,
+}
+
+/// BlockhashStatus is what the block stream tells us about a transaction's recent blockhash
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockhashStatus {
+    Valid { last_valid_block_height: u64 },
+    Expired { last_valid_block_height: u64, block_height: u64 },
+    // not seen, and we've seen every block it could be from
+    NotFound,
+    // not seen, but we may have missed its block, e.g. right after startup or a reconnect
+    Unknown,
+}
+
+/// DurableNonce is the nonce stored in a watched nonce account as of its latest update
+#[derive(Clone, Debug)]
+pub struct DurableNonce {
+    pub nonce: String,
+    pub slot: Slot,
+}
+
+/// StreamHealth is the state of a geyser subscription
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamHealth {
+    pub connected: bool,
+    // unix timestamp in milliseconds, None if the stream never got a message
+    pub last_message_at: Option<u64>,
+    pub last_message_age_ms: Option<u64>,
+}
 
-#[async_trait]
 pub trait SolanaRpc: Send + Sync {
     fn get_next_slot(&self) -> Option<u64>;
-    // return block_time if confirmed, None otherwise
-    async fn confirm_transaction(&self, signature: String) -> Option<UnixTimestamp>;
-    async fn confirm_transaction_with_commitment(&self, signature: String, commitment_config: CommitmentConfig) -> Option<UnixTimestamp>;
-
+    // return the landed transaction if it's been seen in a recent block, None otherwise
+    fn get_landed_transaction(&self, signature: &str) -> Option<LandedTransaction>;
+    fn get_slot_stream_health(&self) -> StreamHealth;
+    fn get_block_stream_health(&self) -> StreamHealth;
+    // return the confirmed block with the highest block height seen, None until the first block
+    fn get_latest_block(&self) -> Option<RecentBlock>;
+    fn get_blockhash_status(&self, blockhash: &str) -> BlockhashStatus;
+    // stream updates to the nonce account, watches are counted so each one needs an unwatch
+    fn watch_nonce_account(&self, nonce_account: &str);
+    fn unwatch_nonce_account(&self, nonce_account: &str);
+    // return the nonce from the latest update to a watched nonce account, None if it wasn't updated since it was watched
+    fn get_durable_nonce(&self, nonce_account: &str) -> Option<DurableNonce>;
 }
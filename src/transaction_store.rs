@@ -1,14 +1,28 @@
 use std::{
     alloc::System,
+    collections::VecDeque,
+    net::SocketAddr,
     sync::Arc,
-    time::{Instant, SystemTime},
+    time::{Duration, Instant, SystemTime},
 };
 
 use cadence_macros::{statsd_count, statsd_time};
 use dashmap::DashMap;
-use solana_sdk::transaction::VersionedTransaction;
+use serde::{Deserialize, Serialize};
+use solana_sdk::{clock::Slot, transaction::VersionedTransaction};
+use tokio::{sync::broadcast, time::sleep};
 use tracing::error;
 
+use crate::auth::ANONYMOUS_CLIENT;
+
+// how long we remember the outcome and send attempts of a transaction after it was last updated
+const OUTCOME_RETENTION: Duration = Duration::from_secs(300);
+// outcomes buffered for subscribers that fall behind before they start lagging
+const OUTCOME_CHANNEL_CAPACITY: usize = 10_000;
+// send attempts kept from the start and the end of a transaction's retries, the ones in between are only counted
+const MAX_FIRST_ATTEMPTS: usize = 100;
+const MAX_LAST_ATTEMPTS: usize = 100;
+
 #[derive(Clone, Debug)]
 pub struct TransactionData {
     pub wire_transaction: Vec<u8>,
@@ -17,6 +31,95 @@ pub struct TransactionData {
     pub sent_at_unix: SystemTime,
     pub retry_count: usize,
     pub max_retries: Option<usize>,
+    // the client that sent the transaction, None when auth is disabled
+    pub client_id: Option<String>,
+    // correlates every log line about the transaction with the request that sent it
+    pub request_id: String,
+    // the transaction can't land once the block height passes this, None until we've seen its blockhash
+    pub last_valid_block_height: Option<u64>,
+    // the transaction advances a durable nonce instead of using a recent blockhash, so it doesn't expire by block height
+    pub durable_nonce: bool,
+    // unix timestamp in milliseconds after which we stop sending it
+    pub deadline: Option<u64>,
+}
+
+/// TransactionStatus is where a transaction is in its lifecycle from the sender's point of view
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TransactionStatus {
+    // sent to leaders, not retried yet
+    Pending,
+    // sent to leaders at least once more by the retry loop
+    Retrying,
+    Landed,
+    // it can't land anymore, the block height passed the last valid block height of its blockhash or its durable
+    // nonce was advanced by another transaction
+    Expired,
+    // we stopped sending it without seeing it land, after max_retries, its deadline, or when we never saw its blockhash
+    Dropped,
+    // the client asked us to stop sending it
+    Cancelled,
+}
+
+/// SendAttempt is a single send of a transaction to a leader
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendAttempt {
+    // unix timestamp in milliseconds
+    pub sent_at: u64,
+    pub slot: Option<Slot>,
+    pub retry_count: usize,
+    pub leader: String,
+    pub tpu_quic: Option<SocketAddr>,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+/// AttemptHistory is what's recorded about the sends of a transaction
+#[derive(Clone, Debug)]
+pub struct AttemptHistory {
+    // the client that sent the transaction, None when auth is disabled
+    pub client_id: Option<String>,
+    pub attempts: Vec<SendAttempt>,
+    // attempts between the first and last ones in attempts that weren't kept
+    pub dropped: usize,
+}
+
+/// SendAttempts are the attempts kept for a signature, long running transactions like durable nonce ones
+/// can be sent thousands of times
+#[derive(Default)]
+struct SendAttempts {
+    client_id: Option<String>,
+    first: Vec<SendAttempt>,
+    last: VecDeque<SendAttempt>,
+    dropped: usize,
+}
+
+impl SendAttempts {
+    fn push(&mut self, attempt: SendAttempt) {
+        if self.first.len() < MAX_FIRST_ATTEMPTS {
+            self.first.push(attempt);
+            return;
+        }
+        if self.last.len() == MAX_LAST_ATTEMPTS {
+            self.last.pop_front();
+            self.dropped += 1;
+        }
+        self.last.push_back(attempt);
+    }
+}
+
+impl TransactionStatus {
+    /// is_finished is true once the sender has stopped working on the transaction
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            TransactionStatus::Landed
+                | TransactionStatus::Expired
+                | TransactionStatus::Dropped
+                | TransactionStatus::Cancelled
+        )
+    }
 }
 
 pub trait TransactionStore: Send + Sync {
@@ -24,19 +127,48 @@ pub trait TransactionStore: Send + Sync {
     fn get_signatures(&self) -> Vec<String>;
     fn remove_transaction(&self, signature: String) -> Option<TransactionData>;
     fn get_transactions(&self) -> Arc<DashMap<String, TransactionData>>;
+    /// get_status returns the status of an in flight transaction, or the recorded outcome of a recently finished one
+    fn get_status(&self, signature: &str) -> Option<TransactionStatus>;
+    fn record_outcome(&self, signature: String, status: TransactionStatus);
+    /// subscribe_outcomes streams every outcome recorded after subscribing
+    fn subscribe_outcomes(&self) -> broadcast::Receiver<(String, TransactionStatus)>;
+    fn record_attempt(&self, signature: &str, client_id: Option<&str>, attempt: SendAttempt);
+    /// get_attempts returns the first and last send attempts recorded for the signature in the order they
+    /// completed, and how many attempts in between weren't kept
+    fn get_attempts(&self, signature: &str) -> Option<AttemptHistory>;
 }
 
 pub struct TransactionStoreImpl {
     transactions: Arc<DashMap<String, TransactionData>>,
+    outcomes: Arc<DashMap<String, (TransactionStatus, Instant)>>,
+    outcome_sender: broadcast::Sender<(String, TransactionStatus)>,
+    attempts: Arc<DashMap<String, (SendAttempts, Instant)>>,
 }
 
 impl TransactionStoreImpl {
     pub fn new() -> Self {
+        let (outcome_sender, _) = broadcast::channel(OUTCOME_CHANNEL_CAPACITY);
         let transaction_store = Self {
             transactions: Arc::new(DashMap::new()),
+            outcomes: Arc::new(DashMap::new()),
+            outcome_sender,
+            attempts: Arc::new(DashMap::new()),
         };
+        transaction_store.clean_history();
         transaction_store
     }
+
+    fn clean_history(&self) {
+        let outcomes = self.outcomes.clone();
+        let attempts = self.attempts.clone();
+        tokio::spawn(async move {
+            loop {
+                outcomes.retain(|_, (_, v)| v.elapsed() < OUTCOME_RETENTION);
+                attempts.retain(|_, (_, v)| v.elapsed() < OUTCOME_RETENTION);
+                sleep(Duration::from_secs(60)).await;
+            }
+        });
+    }
 }
 
 impl TransactionStore for TransactionStoreImpl {
@@ -71,6 +203,61 @@ impl TransactionStore for TransactionStoreImpl {
     fn get_transactions(&self) -> Arc<DashMap<String, TransactionData>> {
         self.transactions.clone()
     }
+    fn get_status(&self, signature: &str) -> Option<TransactionStatus> {
+        if let Some(transaction) = self.transactions.get(signature) {
+            if transaction.retry_count == 0 {
+                return Some(TransactionStatus::Pending);
+            }
+            return Some(TransactionStatus::Retrying);
+        }
+        self.outcomes.get(signature).map(|outcome| outcome.0)
+    }
+    fn record_outcome(&self, signature: String, status: TransactionStatus) {
+        self.outcomes
+            .insert(signature.clone(), (status, Instant::now()));
+        // only fails when nobody is subscribed
+        let _ = self.outcome_sender.send((signature, status));
+    }
+    fn subscribe_outcomes(&self) -> broadcast::Receiver<(String, TransactionStatus)> {
+        self.outcome_sender.subscribe()
+    }
+    fn record_attempt(&self, signature: &str, client_id: Option<&str>, attempt: SendAttempt) {
+        let mut attempts = self
+            .attempts
+            .entry(signature.to_string())
+            .or_insert_with(|| {
+                let attempts = SendAttempts {
+                    client_id: client_id.map(|client_id| client_id.to_string()),
+                    ..Default::default()
+                };
+                (attempts, Instant::now())
+            });
+        attempts.0.push(attempt);
+        attempts.1 = Instant::now();
+    }
+    fn get_attempts(&self, signature: &str) -> Option<AttemptHistory> {
+        self.attempts.get(signature).map(|attempts| {
+            let attempts = &attempts.0;
+            AttemptHistory {
+                client_id: attempts.client_id.clone(),
+                attempts: attempts
+                    .first
+                    .iter()
+                    .chain(&attempts.last)
+                    .cloned()
+                    .collect(),
+                dropped: attempts.dropped,
+            }
+        })
+    }
+}
+
+/// get_client_tag returns the client to attribute the transaction to in metrics
+pub fn get_client_tag(transaction: &TransactionData) -> &str {
+    transaction
+        .client_id
+        .as_deref()
+        .unwrap_or(ANONYMOUS_CLIENT)
 }
 
 pub fn get_signature(transaction: &TransactionData) -> Option<String> {
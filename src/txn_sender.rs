@@ -1,27 +1,117 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
 
 use cadence_macros::{statsd_count, statsd_gauge, statsd_time};
+use dashmap::{mapref::entry::Entry, DashMap};
 use solana_client::{
     connection_cache::ConnectionCache, nonblocking::tpu_connection::TpuConnection,
 };
 use solana_program_runtime::compute_budget::ComputeBudget;
-use solana_sdk::{commitment_config::CommitmentConfig, transaction::VersionedTransaction};
+use solana_rpc_client_api::response::RpcContactInfo;
+use solana_sdk::{clock::Slot, pubkey::Pubkey, transaction::VersionedTransaction};
 use tokio::{
     runtime::{Builder, Runtime},
+    sync::oneshot,
     time::sleep,
 };
 use tonic::async_trait;
-use tracing::{error, warn};
+use tracing::{debug, error, info_span, warn, Instrument};
 
 use crate::{
     leader_tracker::LeaderTracker,
-    solana_rpc::SolanaRpc,
-    transaction_store::{get_signature, TransactionData, TransactionStore},
+    rate_limiter::RateLimiter,
+    solana_rpc::{BlockhashStatus, SolanaRpc},
+    transaction_store::{
+        get_client_tag, get_signature, SendAttempt, TransactionData, TransactionStatus,
+        TransactionStore,
+    },
+    utils::now_unix_millis,
 };
 
+// how long the retry loop waits between passes unless changed through the admin api
+pub const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+// how often the confirmation watcher checks whether the transaction landed or expired
+const WATCH_INTERVAL: Duration = Duration::from_millis(200);
+// transactions whose blockhash we never see are watched for this long, in practice if a tx doesn't land in
+// less than 60 seconds it's probably not going to land
+const MAX_WATCH_WITHOUT_BLOCKHASH: Duration = Duration::from_secs(60);
+// blockhashes are valid for 150 blocks, about 60 seconds, so transactions that don't use a durable nonce are given
+// up on after this long even if the block stream stalls and we never see their last valid block height pass
+const MAX_WATCH_TIME: Duration = Duration::from_secs(90);
+// client retry budgets cap the retries sent within this long, so transactions that have been queued for a long time
+// don't use up the budget for good
+const RETRY_BUDGET_WINDOW: Duration = Duration::from_secs(10);
+
+/// WatchOutcome is why the confirmation watcher stopped watching a transaction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum WatchOutcome {
+    Landed,
+    // the block height passed the transaction's last valid block height
+    Expired,
+    // we never saw its blockhash and gave up after MAX_WATCH_WITHOUT_BLOCKHASH, or it outlived MAX_WATCH_TIME
+    TimedOut,
+    // another transaction advanced the durable nonce it uses
+    NonceAdvanced,
+    DeadlinePassed,
+}
+
+/// SentTransaction identifies a wire transaction in a send to leaders
+#[derive(Clone, Debug)]
+struct SentTransaction {
+    signature: String,
+    request_id: String,
+    client_id: Option<String>,
+    retry_count: usize,
+}
+
+impl SentTransaction {
+    fn new(transaction_data: &TransactionData) -> Option<Self> {
+        Some(Self {
+            signature: get_signature(transaction_data)?,
+            request_id: transaction_data.request_id.clone(),
+            client_id: transaction_data.client_id.clone(),
+            retry_count: transaction_data.retry_count,
+        })
+    }
+}
+
 #[async_trait]
 pub trait TxnSender: Send + Sync {
-    fn send_transaction(&self, txn: TransactionData, commitment_config: Option<CommitmentConfig>);
+    fn send_transaction(&self, txn: TransactionData);
+    /// send_transactions sends all transactions to each leader in a single batch
+    fn send_transactions(&self, txns: Vec<TransactionData>);
+    /// cancel_transaction stops retrying the transaction and resolves its confirmation watcher,
+    /// returns false if the sender wasn't working on it anymore
+    fn cancel_transaction(&self, signature: &str) -> bool;
+    /// is_tracking is true while the sender is retrying the transaction or watching for it to land
+    fn is_tracking(&self, signature: &str) -> bool;
+    /// get_client_id returns the client that sent a transaction the sender is tracking, None if it isn't tracking it
+    fn get_client_id(&self, signature: &str) -> Option<Option<String>>;
+    /// set_paused stops or resumes sending to leaders, paused transactions stay queued and are retried once resumed
+    fn set_paused(&self, paused: bool);
+    fn is_paused(&self) -> bool;
+    fn get_retry_interval(&self) -> Duration;
+    fn set_retry_interval(&self, retry_interval: Duration);
+    /// drain_retry_queue stops retrying every queued transaction and returns how many there were, they're
+    /// still watched until they land or expire
+    fn drain_retry_queue(&self) -> usize;
+}
+
+/// ConfirmationWatcher is the handle to the task watching for a transaction to land
+struct ConfirmationWatcher {
+    // a signature that's cancelled and sent again gets a new watcher with a new id
+    id: u64,
+    // resolving it stops the watcher
+    cancel_sender: oneshot::Sender<()>,
+    // the client that sent the transaction
+    client_id: Option<String>,
 }
 
 pub struct TxnSenderImpl {
@@ -29,7 +119,13 @@ pub struct TxnSenderImpl {
     transaction_store: Arc<dyn TransactionStore>,
     connection_cache: Arc<ConnectionCache>,
     solana_rpc: Arc<dyn SolanaRpc>,
+    rate_limiter: Arc<dyn RateLimiter>,
     txn_sender_runtime: Arc<Runtime>,
+    confirmation_watchers: Arc<DashMap<String, ConfirmationWatcher>>,
+    next_watcher_id: AtomicU64,
+    // set through the admin api
+    paused: Arc<AtomicBool>,
+    retry_interval_ms: Arc<AtomicU64>,
 }
 
 impl TxnSenderImpl {
@@ -38,6 +134,7 @@ impl TxnSenderImpl {
         transaction_store: Arc<dyn TransactionStore>,
         connection_cache: Arc<ConnectionCache>,
         solana_rpc: Arc<dyn SolanaRpc>,
+        rate_limiter: Arc<dyn RateLimiter>,
         txn_sender_threads: usize,
     ) -> Self {
         let txn_sender_runtime = Builder::new_multi_thread()
@@ -50,7 +147,12 @@ impl TxnSenderImpl {
             transaction_store,
             connection_cache,
             solana_rpc,
+            rate_limiter,
             txn_sender_runtime: Arc::new(txn_sender_runtime),
+            confirmation_watchers: Arc::new(DashMap::new()),
+            next_watcher_id: AtomicU64::new(0),
+            paused: Arc::new(AtomicBool::new(false)),
+            retry_interval_ms: Arc::new(AtomicU64::new(DEFAULT_RETRY_INTERVAL.as_millis() as u64)),
         };
         txn_sender.retry_transactions();
         txn_sender
@@ -61,83 +163,159 @@ impl TxnSenderImpl {
         let transaction_store = self.transaction_store.clone();
         let connection_cache = self.connection_cache.clone();
         let txn_sender_runtime = self.txn_sender_runtime.clone();
+        let solana_rpc = self.solana_rpc.clone();
+        let rate_limiter = self.rate_limiter.clone();
+        let paused = self.paused.clone();
+        let retry_interval_ms = self.retry_interval_ms.clone();
         tokio::spawn(async move {
+            // when each retry in the last RETRY_BUDGET_WINDOW was sent, for clients with a retry budget
+            let mut recent_retries: HashMap<String, VecDeque<Instant>> = HashMap::new();
             loop {
+                let retry_interval = Duration::from_millis(retry_interval_ms.load(Ordering::Relaxed));
+                if paused.load(Ordering::Relaxed) {
+                    // queued transactions keep their retry counts until sending is resumed
+                    statsd_gauge!(
+                        "transaction_retry_queue_length",
+                        transaction_store.get_transactions().len() as u64
+                    );
+                    sleep(retry_interval).await;
+                    continue;
+                }
                 let mut transactions_reached_max_retries = vec![];
+                // retries that left the window no longer count towards their client's retry budget
+                for retries in recent_retries.values_mut() {
+                    while retries
+                        .front()
+                        .map_or(false, |sent_at| sent_at.elapsed() >= RETRY_BUDGET_WINDOW)
+                    {
+                        retries.pop_front();
+                    }
+                }
+                recent_retries.retain(|_, retries| !retries.is_empty());
+                // signature, client and retry count of the transactions that can be retried in this pass
+                let mut retryable = vec![];
+                let block_height = solana_rpc.get_latest_block().map(|block| block.block_height);
+                let now = now_unix_millis();
                 let transcations = transaction_store.get_transactions();
                 let transaction_retry_queue_length = transcations.len();
                 let mut wire_transactions = vec![];
+                let mut sent_transactions = vec![];
                 // get wire transactions and push transactions that reached max retries to transactions_reached_max_retries
                 for mut transaction_data in transcations.iter_mut() {
+                    if !transaction_data.durable_nonce && transaction_data.last_valid_block_height.is_none() {
+                        transaction_data.last_valid_block_height =
+                            get_last_valid_block_height(&solana_rpc, &transaction_data.versioned_transaction);
+                    }
+                    let expired = transaction_data
+                        .last_valid_block_height
+                        .zip(block_height)
+                        .map_or(false, |(last_valid_block_height, block_height)| block_height > last_valid_block_height);
+                    let deadline_passed = transaction_data.deadline.map_or(false, |deadline| now >= deadline);
+                    let timed_out =
+                        !transaction_data.durable_nonce && transaction_data.sent_at.elapsed() >= MAX_WATCH_TIME;
+                    let client = get_client_tag(&transaction_data).to_string();
+                    if expired || deadline_passed || timed_out {
+                        // its confirmation watcher removes it and records the outcome
+                        continue;
+                    }
                     if transaction_data.retry_count
                         >= transaction_data.max_retries.unwrap_or(usize::MAX)
                     {
                         transactions_reached_max_retries
                             .push(get_signature(&transaction_data).unwrap());
                     } else {
-                        transaction_data.retry_count += 1;
-                        wire_transactions.push(transaction_data.wire_transaction.clone());
+                        retryable.push((
+                            get_signature(&transaction_data).unwrap(),
+                            client,
+                            transaction_data.retry_count,
+                        ));
                     }
                 }
-                // send wire transactions to leaders
-                let wire_transactions = Arc::new(wire_transactions).clone();
-                for leader in leader_tracker.get_leaders() {
-                    if leader.tpu_quic.is_none() {
-                        error!("leader {:?} has no tpu_quic", leader);
+                // the least retried go first, so a client over its budget doesn't keep skipping the same transactions
+                retryable.sort_by_key(|(_, _, retry_count)| *retry_count);
+                for (signature, client, _) in retryable {
+                    let retry_budget = rate_limiter.retry_budget(&client);
+                    if let Some(retry_budget) = retry_budget {
+                        let retries = recent_retries.get(&client).map_or(0, |retries| retries.len());
+                        if retries >= retry_budget {
+                            // retried once the client's earlier retries leave the window, skipping doesn't count
+                            // towards max_retries
+                            statsd_count!("retry_budget_exceeded", 1, "client" => &client);
+                            continue;
+                        }
+                    }
+                    let Some(mut transaction_data) = transcations.get_mut(&signature) else {
                         continue;
+                    };
+                    if retry_budget.is_some() {
+                        recent_retries.entry(client).or_default().push_back(Instant::now());
                     }
-                    let wire_transactions = wire_transactions.clone();
-                    let connection_cache = connection_cache.clone();
-                    txn_sender_runtime.spawn(async move {
-                        for i in 0..3 {
-                            let conn = connection_cache
-                                .get_nonblocking_connection(&leader.tpu_quic.unwrap());
-                            if let Err(e) = conn.send_data_batch(&wire_transactions.clone()).await {
-                                if i == 2 {
-                                    error!(
-                                        "Failed to send transaction batch to {:?}: {}",
-                                        leader, e
-                                    );
-                                } else {
-                                    warn!(
-                                        "Retrying to send transaction batch to {:?}: {}",
-                                        leader, e
-                                    );
-                                }
-                            } else {
-                                return;
-                            }
-                        }
-                    });
+                    transaction_data.retry_count += 1;
+                    wire_transactions.push(transaction_data.wire_transaction.clone());
+                    sent_transactions.push(SentTransaction::new(&transaction_data).unwrap());
                 }
-                // remove transactions that reached max retries
+                // send wire transactions to leaders
+                send_batch_to_leaders(
+                    leader_tracker.get_leaders(),
+                    &connection_cache,
+                    &txn_sender_runtime,
+                    &transaction_store,
+                    solana_rpc.get_next_slot(),
+                    Arc::new(sent_transactions),
+                    Arc::new(wire_transactions),
+                );
+                // remove transactions that reached max retries, they can still land so their confirmation watcher
+                // records the outcome
                 for signature in transactions_reached_max_retries {
-                    statsd_count!("transactions_reached_max_retries", 1);
-                    let transaction_data = transaction_store.remove_transaction(signature);
-                    if let Some(transaction_data) = transaction_data {
-                        let priority_fees =
-                            compute_priority_fee(&transaction_data.versioned_transaction)
-                                .map_or(false, |fee| fee > 0)
-                                .to_string();
-                        statsd_count!("transactions_not_landed", 1, "priority_fees" => &priority_fees);
-                        statsd_gauge!(
-                            "transaction_retry_queue_length",
-                            transaction_retry_queue_length as u64
+                    if let Some(transaction_data) = transaction_store.remove_transaction(signature) {
+                        debug!(
+                            request_id = %transaction_data.request_id,
+                            signature = %get_signature(&transaction_data).unwrap_or_default(),
+                            retry_count = transaction_data.retry_count,
+                            "transaction reached max retries"
                         );
+                        let client = get_client_tag(&transaction_data);
+                        statsd_count!("transactions_reached_max_retries", 1, "client" => client);
                     }
                 }
-                sleep(Duration::from_secs(1)).await;
+                statsd_gauge!(
+                    "transaction_retry_queue_length",
+                    transaction_retry_queue_length as u64
+                );
+                sleep(retry_interval).await;
             }
         });
     }
-    fn track_transaction(&self, transaction_data: &TransactionData, commitment_config: Option<CommitmentConfig>) {
-        let commitment_config = commitment_config.unwrap_or_else(|| CommitmentConfig::default());
+    /// track_transaction queues the transaction for retries and starts its confirmation watcher, returns false
+    /// without doing either if the transaction is already being tracked. The in flight slot the transaction
+    /// acquired from the rate limiter is released once the watcher stops, or right away for a duplicate
+    fn track_transaction(&self, transaction_data: &TransactionData) -> bool {
         let sent_at = transaction_data.sent_at.clone();
         let signature = get_signature(transaction_data);
         if signature.is_none() {
-            return;
+            return false;
         }
         let signature = signature.unwrap();
+        let client = get_client_tag(transaction_data).to_string();
+        let watcher_id = self.next_watcher_id.fetch_add(1, Ordering::Relaxed);
+        // claiming the watcher slot first means concurrent resubmissions can't both start sending
+        let cancel_receiver = match self.confirmation_watchers.entry(signature.clone()) {
+            Entry::Occupied(_) => {
+                debug!(request_id = %transaction_data.request_id, signature = %signature, "transaction is already being tracked");
+                statsd_count!("duplicate_transactions", 1, "client" => &client);
+                self.rate_limiter.release(&client);
+                return false;
+            }
+            Entry::Vacant(entry) => {
+                let (cancel_sender, cancel_receiver) = oneshot::channel();
+                entry.insert(ConfirmationWatcher {
+                    id: watcher_id,
+                    cancel_sender,
+                    client_id: transaction_data.client_id.clone(),
+                });
+                cancel_receiver
+            }
+        };
         if transaction_data.max_retries.unwrap_or(1) > 0 {
             self.transaction_store
                 .add_transaction(transaction_data.clone());
@@ -145,30 +323,297 @@ impl TxnSenderImpl {
         let priority_fees = compute_priority_fee(&transaction_data.versioned_transaction)
             .map_or(false, |fee| fee > 0)
             .to_string();
+        let versioned_transaction = transaction_data.versioned_transaction.clone();
+        let last_valid_block_height = transaction_data.last_valid_block_height;
+        let durable_nonce = transaction_data.durable_nonce;
+        let deadline = transaction_data.deadline;
         let solana_rpc = self.solana_rpc.clone();
         let transaction_store = self.transaction_store.clone();
+        let confirmation_watchers = self.confirmation_watchers.clone();
+        let rate_limiter = self.rate_limiter.clone();
+        let span = info_span!(
+            "track_transaction",
+            request_id = %transaction_data.request_id,
+            signature = %signature,
+            client = %client
+        );
         self.txn_sender_runtime.spawn(async move {
-            let confirmed_at = solana_rpc.confirm_transaction_with_commitment(signature.clone(), commitment_config).await;
-            transaction_store.remove_transaction(signature);
-            if let Some(confirmed_at) = confirmed_at {
-                statsd_count!("transactions_landed", 1, "priority_fees" => &priority_fees);
-                statsd_time!("transaction_land_time", sent_at.elapsed(), "priority_fees" => &priority_fees);
-                // This code doesn't behave as expected, it returns very low times and sometimes negative times, maybe the txns land extremely fast, but it seems fishy.
-                // match unix_to_time(confirmed_at).duration_since(sent_at_unix) {
-                //     Ok(land_time) => {
-                //         statsd_time!("transaction_land_time", land_time.as_secs() as u64);
-                //     }
-                //     Err(e) => {
-                //         error!("Error computing land time: {}", e);
-                //     }
-                // }
-            } else {
-                statsd_count!("transactions_not_landed", 1, "priority_fees" => &priority_fees);
+            debug!("watching for transaction to land");
+            let outcome = tokio::select! {
+                outcome = watch_transaction(&solana_rpc, &signature, &versioned_transaction, durable_nonce, last_valid_block_height, deadline, sent_at) => outcome,
+                _ = cancel_receiver => {
+                    // the outcome is recorded by whoever cancelled the watcher
+                    debug!("transaction cancelled");
+                    statsd_count!("transactions_cancelled", 1, "priority_fees" => &priority_fees, "client" => &client);
+                    rate_limiter.release(&client);
+                    return;
+                }
+            };
+            rate_limiter.release(&client);
+            // the transaction may have been cancelled, and sent again, since the watch ended. The cancel recorded
+            // its outcome and a new watcher owns the transaction now
+            if confirmation_watchers
+                .remove_if(&signature, |_, watcher| watcher.id == watcher_id)
+                .is_none()
+            {
+                return;
+            }
+            transaction_store.remove_transaction(signature.clone());
+            match outcome {
+                WatchOutcome::Landed => {
+                    debug!(land_time_ms = sent_at.elapsed().as_millis() as u64, "transaction landed");
+                    transaction_store.record_outcome(signature, TransactionStatus::Landed);
+                    statsd_count!("transactions_landed", 1, "priority_fees" => &priority_fees, "client" => &client);
+                    statsd_time!("transaction_land_time", sent_at.elapsed(), "priority_fees" => &priority_fees, "client" => &client);
+                    // This code doesn't behave as expected, it returns very low times and sometimes negative times, maybe the txns land extremely fast, but it seems fishy.
+                    // match unix_to_time(confirmed_at).duration_since(sent_at_unix) {
+                    //     Ok(land_time) => {
+                    //         statsd_time!("transaction_land_time", land_time.as_secs() as u64);
+                    //     }
+                    //     Err(e) => {
+                    //         error!("Error computing land time: {}", e);
+                    //     }
+                    // }
+                }
+                WatchOutcome::Expired | WatchOutcome::NonceAdvanced => {
+                    debug!(outcome = ?outcome, "transaction expired");
+                    transaction_store.record_outcome(signature, TransactionStatus::Expired);
+                    if outcome == WatchOutcome::NonceAdvanced {
+                        statsd_count!("transactions_nonce_advanced", 1, "client" => &client);
+                    } else {
+                        statsd_count!("transactions_expired", 1, "client" => &client);
+                    }
+                    statsd_count!("transactions_not_landed", 1, "priority_fees" => &priority_fees, "client" => &client);
+                }
+                WatchOutcome::TimedOut | WatchOutcome::DeadlinePassed => {
+                    debug!(outcome = ?outcome, "transaction didn't land");
+                    transaction_store.record_outcome(signature, TransactionStatus::Dropped);
+                    if outcome == WatchOutcome::DeadlinePassed {
+                        statsd_count!("transactions_deadline_passed", 1, "client" => &client);
+                    }
+                    statsd_count!("transactions_not_landed", 1, "priority_fees" => &priority_fees, "client" => &client);
+                }
+            }
+        }.instrument(span));
+        true
+    }
+}
+
+/// watch_transaction waits for the transaction to land or for the block height to pass its last valid block
+/// height, transactions whose blockhash we never see are given up on after MAX_WATCH_WITHOUT_BLOCKHASH and any
+/// others MAX_WATCH_TIME after they were sent. Durable nonce transactions don't expire by block height, they're
+/// watched until their nonce account is advanced by another transaction. Any transaction is given up on once its
+/// deadline passes
+async fn watch_transaction(
+    solana_rpc: &Arc<dyn SolanaRpc>,
+    signature: &str,
+    transaction: &VersionedTransaction,
+    durable_nonce: bool,
+    mut last_valid_block_height: Option<u64>,
+    deadline: Option<u64>,
+    sent_at: Instant,
+) -> WatchOutcome {
+    let start = Instant::now();
+    // nonce accounts loaded from a lookup table can't be watched, those transactions are watched until their deadline
+    let nonce_account_watch = get_nonce_account(transaction).map(|nonce_account| NonceAccountWatch::new(solana_rpc.clone(), nonce_account.to_string()));
+    loop {
+        // blocks are streamed in order, so a transaction that landed before it expired is seen first
+        if solana_rpc.get_landed_transaction(signature).is_some() {
+            return WatchOutcome::Landed;
+        }
+        if deadline.map_or(false, |deadline| now_unix_millis() >= deadline) {
+            return WatchOutcome::DeadlinePassed;
+        }
+        if durable_nonce {
+            if let Some(nonce_account_watch) = &nonce_account_watch {
+                if nonce_account_watch.advanced(transaction) {
+                    // the block that advanced it may be the one our transaction landed in
+                    if solana_rpc.get_landed_transaction(signature).is_some() {
+                        return WatchOutcome::Landed;
+                    }
+                    return WatchOutcome::NonceAdvanced;
+                }
+            }
+            sleep(WATCH_INTERVAL).await;
+            continue;
+        }
+        if sent_at.elapsed() >= MAX_WATCH_TIME {
+            return WatchOutcome::TimedOut;
+        }
+        if last_valid_block_height.is_none() {
+            last_valid_block_height = get_last_valid_block_height(solana_rpc, transaction);
+        }
+        match last_valid_block_height {
+            Some(last_valid_block_height) => {
+                let block_height = solana_rpc.get_latest_block().map(|block| block.block_height);
+                if block_height.map_or(false, |block_height| block_height > last_valid_block_height) {
+                    return WatchOutcome::Expired;
+                }
+            }
+            None if start.elapsed() >= MAX_WATCH_WITHOUT_BLOCKHASH => return WatchOutcome::TimedOut,
+            None => {}
+        }
+        sleep(WATCH_INTERVAL).await;
+    }
+}
+
+/// NonceAccountWatch streams updates to a nonce account for as long as it's alive, including when the
+/// confirmation watcher is cancelled
+struct NonceAccountWatch {
+    solana_rpc: Arc<dyn SolanaRpc>,
+    nonce_account: String,
+}
+
+impl NonceAccountWatch {
+    fn new(solana_rpc: Arc<dyn SolanaRpc>, nonce_account: String) -> Self {
+        solana_rpc.watch_nonce_account(&nonce_account);
+        Self {
+            solana_rpc,
+            nonce_account,
+        }
+    }
+
+    /// advanced is true once the nonce account holds a different nonce than the transaction uses, and the block
+    /// stream caught up to that update so a transaction of ours that advanced it would have been seen landing
+    fn advanced(&self, transaction: &VersionedTransaction) -> bool {
+        let Some(durable_nonce) = self.solana_rpc.get_durable_nonce(&self.nonce_account) else {
+            return false;
+        };
+        durable_nonce.nonce != transaction.message.recent_blockhash().to_string()
+            && self
+                .solana_rpc
+                .get_latest_block()
+                .map_or(false, |block| block.slot >= durable_nonce.slot)
+    }
+}
+
+impl Drop for NonceAccountWatch {
+    fn drop(&mut self) {
+        self.solana_rpc.unwatch_nonce_account(&self.nonce_account);
+    }
+}
+
+/// get_nonce_account returns the nonce account advanced by the transaction's first instruction, None if the
+/// transaction doesn't use a durable nonce or the account is loaded from a lookup table
+pub fn get_nonce_account(transaction: &VersionedTransaction) -> Option<&Pubkey> {
+    if !transaction.uses_durable_nonce() {
+        return None;
+    }
+    let instruction = transaction.message.instructions().first()?;
+    let index = *instruction.accounts.first()?;
+    transaction.message.static_account_keys().get(index as usize)
+}
+
+/// get_last_valid_block_height looks up the last valid block height of the transaction's blockhash, None if the
+/// blockhash wasn't seen on the block stream
+fn get_last_valid_block_height(
+    solana_rpc: &Arc<dyn SolanaRpc>,
+    transaction: &VersionedTransaction,
+) -> Option<u64> {
+    let blockhash = transaction.message.recent_blockhash().to_string();
+    match solana_rpc.get_blockhash_status(&blockhash) {
+        BlockhashStatus::Valid {
+            last_valid_block_height,
+        }
+        | BlockhashStatus::Expired {
+            last_valid_block_height,
+            ..
+        } => Some(last_valid_block_height),
+        BlockhashStatus::NotFound | BlockhashStatus::Unknown => None,
+    }
+}
+
+/// send_batch_to_leaders sends the wire transactions to each leader with one send_data_batch call per leader,
+/// sent_transactions identifies each wire transaction in the same order
+fn send_batch_to_leaders(
+    leaders: Vec<RpcContactInfo>,
+    connection_cache: &Arc<ConnectionCache>,
+    txn_sender_runtime: &Runtime,
+    transaction_store: &Arc<dyn TransactionStore>,
+    slot: Option<Slot>,
+    sent_transactions: Arc<Vec<SentTransaction>>,
+    wire_transactions: Arc<Vec<Vec<u8>>>,
+) {
+    for leader in leaders {
+        if leader.tpu_quic.is_none() {
+            error!("leader {:?} has no tpu_quic", leader);
+            continue;
+        }
+        let wire_transactions = wire_transactions.clone();
+        let sent_transactions = sent_transactions.clone();
+        let connection_cache = connection_cache.clone();
+        let transaction_store = transaction_store.clone();
+        txn_sender_runtime.spawn(async move {
+            let sent_at = SystemTime::now();
+            for i in 0..3 {
+                let conn = connection_cache.get_nonblocking_connection(&leader.tpu_quic.unwrap());
+                if let Err(e) = conn.send_data_batch(&wire_transactions).await {
+                    if i == 2 {
+                        error!("Failed to send transaction batch to {:?}: {}", leader, e);
+                        record_send_attempts(
+                            &transaction_store,
+                            &sent_transactions,
+                            &leader,
+                            slot,
+                            sent_at,
+                            Some(e.to_string()),
+                        );
+                    } else {
+                        warn!("Retrying to send transaction batch to {:?}: {}", leader, e);
+                    }
+                } else {
+                    record_send_attempts(
+                        &transaction_store,
+                        &sent_transactions,
+                        &leader,
+                        slot,
+                        sent_at,
+                        None,
+                    );
+                    return;
+                }
             }
         });
     }
 }
 
+fn record_send_attempts(
+    transaction_store: &Arc<dyn TransactionStore>,
+    sent_transactions: &[SentTransaction],
+    leader: &RpcContactInfo,
+    slot: Option<Slot>,
+    sent_at: SystemTime,
+    error: Option<String>,
+) {
+    let sent_at = sent_at
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64);
+    for sent_transaction in sent_transactions {
+        if let Some(error) = &error {
+            debug!(
+                request_id = %sent_transaction.request_id,
+                signature = %sent_transaction.signature,
+                leader = %leader.pubkey,
+                "failed to send transaction: {}",
+                error
+            );
+        }
+        transaction_store.record_attempt(
+            &sent_transaction.signature,
+            sent_transaction.client_id.as_deref(),
+            SendAttempt {
+                sent_at,
+                slot,
+                retry_count: sent_transaction.retry_count,
+                leader: leader.pubkey.clone(),
+                tpu_quic: leader.tpu_quic,
+                success: error.is_none(),
+                error: error.clone(),
+            },
+        );
+    }
+}
+
 pub fn compute_priority_fee(transaction: &VersionedTransaction) -> Option<u64> {
     let mut compute_budget = ComputeBudget::default();
     if let Err(e) = transaction.sanitize() {
@@ -195,30 +640,135 @@ pub fn compute_priority_fee(transaction: &VersionedTransaction) -> Option<u64> {
 
 #[async_trait]
 impl TxnSender for TxnSenderImpl {
-    fn send_transaction(&self, transaction_data: TransactionData, commitment_config: Option<CommitmentConfig>) {
-        self.track_transaction(&transaction_data, commitment_config);
+    fn send_transaction(&self, transaction_data: TransactionData) {
+        // paused transactions are sent by the retry loop once sending is resumed
+        if !self.track_transaction(&transaction_data) || self.is_paused() {
+            return;
+        }
+        let sent_transaction = SentTransaction::new(&transaction_data);
+        if sent_transaction.is_none() {
+            return;
+        }
+        let sent_transaction = sent_transaction.unwrap();
+        let span = info_span!(
+            "send_transaction",
+            request_id = %sent_transaction.request_id,
+            signature = %sent_transaction.signature
+        );
+        let sent_transactions = Arc::new(vec![sent_transaction]);
+        let slot = self.solana_rpc.get_next_slot();
         for leader in self.leader_tracker.get_leaders() {
             if leader.tpu_quic.is_none() {
                 error!("leader {:?} has no tpu_quic", leader);
                 continue;
             }
             let connection_cache = self.connection_cache.clone();
+            let transaction_store = self.transaction_store.clone();
+            let sent_transactions = sent_transactions.clone();
             let wire_transaction = transaction_data.wire_transaction.clone();
             self.txn_sender_runtime.spawn(async move {
+                let sent_at = SystemTime::now();
                 for i in 0..3 {
                     let conn =
                         connection_cache.get_nonblocking_connection(&leader.tpu_quic.unwrap());
                     if let Err(e) = conn.send_data(&wire_transaction).await {
                         if i == 2 {
                             error!("Failed to send transaction to {:?}: {}", leader, e);
+                            record_send_attempts(
+                                &transaction_store,
+                                &sent_transactions,
+                                &leader,
+                                slot,
+                                sent_at,
+                                Some(e.to_string()),
+                            );
                         } else {
                             warn!("Retrying to send transaction to {:?}: {}", leader, e);
                         }
                     } else {
+                        record_send_attempts(
+                            &transaction_store,
+                            &sent_transactions,
+                            &leader,
+                            slot,
+                            sent_at,
+                            None,
+                        );
                         return;
                     }
                 }
-            });
+            }.instrument(span.clone()));
+        }
+    }
+    fn send_transactions(&self, transactions: Vec<TransactionData>) {
+        let mut wire_transactions = Vec::with_capacity(transactions.len());
+        let mut sent_transactions = Vec::with_capacity(transactions.len());
+        for transaction_data in transactions {
+            if !self.track_transaction(&transaction_data) {
+                continue;
+            }
+            if let Some(sent_transaction) = SentTransaction::new(&transaction_data) {
+                sent_transactions.push(sent_transaction);
+                wire_transactions.push(transaction_data.wire_transaction);
+            }
+        }
+        if wire_transactions.is_empty() || self.is_paused() {
+            return;
+        }
+        send_batch_to_leaders(
+            self.leader_tracker.get_leaders(),
+            &self.connection_cache,
+            &self.txn_sender_runtime,
+            &self.transaction_store,
+            self.solana_rpc.get_next_slot(),
+            Arc::new(sent_transactions),
+            Arc::new(wire_transactions),
+        );
+    }
+    fn cancel_transaction(&self, signature: &str) -> bool {
+        let removed = self
+            .transaction_store
+            .remove_transaction(signature.to_string())
+            .is_some();
+        let watcher = self.confirmation_watchers.remove(signature);
+        if !removed && watcher.is_none() {
+            return false;
+        }
+        self.transaction_store
+            .record_outcome(signature.to_string(), TransactionStatus::Cancelled);
+        if let Some((_, watcher)) = watcher {
+            let _ = watcher.cancel_sender.send(());
+        }
+        true
+    }
+    fn is_tracking(&self, signature: &str) -> bool {
+        self.confirmation_watchers.contains_key(signature)
+    }
+    fn get_client_id(&self, signature: &str) -> Option<Option<String>> {
+        self.confirmation_watchers
+            .get(signature)
+            .map(|watcher| watcher.client_id.clone())
+    }
+    fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+    fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+    fn get_retry_interval(&self) -> Duration {
+        Duration::from_millis(self.retry_interval_ms.load(Ordering::Relaxed))
+    }
+    fn set_retry_interval(&self, retry_interval: Duration) {
+        self.retry_interval_ms
+            .store(retry_interval.as_millis() as u64, Ordering::Relaxed);
+    }
+    fn drain_retry_queue(&self) -> usize {
+        let mut drained = 0;
+        for signature in self.transaction_store.get_signatures() {
+            if self.transaction_store.remove_transaction(signature).is_some() {
+                drained += 1;
+            }
         }
+        drained
     }
 }
@@ -4,6 +4,12 @@ pub fn unix_to_time(unix_timestamp: i64) -> SystemTime {
     SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(unix_timestamp as u64)
 }
 
+pub fn now_unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
 pub fn elapsed(start: SystemTime) -> u64 {
     start.elapsed().unwrap().as_millis() as u64
 }